@@ -0,0 +1,219 @@
+use std::{fmt, path::Path};
+
+use bevy::prelude::*;
+use serde::{
+    de::{MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+
+use crate::entities::character::AnimationProperties;
+
+/// Json export of an Aseprite sprite sheet (File -> Export Sprite Sheet -> Output -> JSON Data).
+/// Both the "Hash" and the "Array" layout are supported
+#[derive(Deserialize, Asset, TypePath)]
+pub struct AsepriteSheet {
+    pub frames: AsepriteFrames,
+    pub meta: AsepriteMeta,
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+pub struct AsepriteRect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+pub struct AsepriteSize {
+    pub w: f32,
+    pub h: f32,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct AsepriteFrame {
+    pub frame: AsepriteRect,
+    /// Duration of the frame in milliseconds
+    pub duration: u32,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AsepriteDirection {
+    #[default]
+    #[serde(rename = "forward")]
+    Forward,
+    #[serde(rename = "reverse")]
+    Reverse,
+    #[serde(rename = "pingpong")]
+    PingPong,
+    #[serde(rename = "pingpong_reverse")]
+    PingPongReverse,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct AsepriteTag {
+    pub name: String,
+    pub from: usize,
+    pub to: usize,
+    #[serde(default)]
+    pub direction: AsepriteDirection,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AsepriteMeta {
+    /// Path of the image relative to the json file
+    pub image: String,
+    pub size: AsepriteSize,
+    #[serde(default)]
+    pub frame_tags: Vec<AsepriteTag>,
+}
+
+/// Frames in the order they were exported
+pub struct AsepriteFrames(pub Vec<AsepriteFrame>);
+
+impl<'de> Deserialize<'de> for AsepriteFrames {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct FramesVisitor;
+
+        impl<'de> Visitor<'de> for FramesVisitor {
+            type Value = AsepriteFrames;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an array or a map of aseprite frames")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let mut frames = Vec::new();
+                while let Some(frame) = seq.next_element()? {
+                    frames.push(frame);
+                }
+                Ok(AsepriteFrames(frames))
+            }
+
+            // The hash layout is keyed by file name. Keep the export order instead of sorting
+            // the keys since the frame tags index into it
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut frames = Vec::new();
+                while let Some((_name, frame)) = map.next_entry::<String, AsepriteFrame>()? {
+                    frames.push(frame);
+                }
+                Ok(AsepriteFrames(frames))
+            }
+        }
+
+        deserializer.deserialize_any(FramesVisitor)
+    }
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+impl AsepriteSheet {
+    /// Path of the sprite sheet image relative to the asset folder
+    pub fn image_path(&self, json_path: &str) -> String {
+        Path::new(json_path)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(&self.meta.image)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    pub fn layout(&self) -> TextureAtlasLayout {
+        let mut layout =
+            TextureAtlasLayout::new_empty(Vec2::new(self.meta.size.w, self.meta.size.h));
+        for frame in &self.frames.0 {
+            let rect = frame.frame;
+            layout.add_texture(Rect::new(rect.x, rect.y, rect.x + rect.w, rect.y + rect.h));
+        }
+        layout
+    }
+
+    /// Whether the tag covers at least one frame and only existing ones
+    pub fn is_valid_tag(&self, tag: &AsepriteTag) -> bool {
+        tag.from <= tag.to && tag.to < self.frames.0.len()
+    }
+
+    /// Frame indices of a valid tag in playing order
+    pub fn tag_frames(&self, tag: &AsepriteTag) -> Vec<usize> {
+        let forward: Vec<usize> = (tag.from..=tag.to).collect();
+        let mut reverse = forward.clone();
+        reverse.reverse();
+        match tag.direction {
+            AsepriteDirection::Forward => forward,
+            AsepriteDirection::Reverse => reverse,
+            // Don't repeat the turning frames
+            AsepriteDirection::PingPong => forward
+                .iter()
+                .chain(reverse.iter().skip(1).take(reverse.len().saturating_sub(2)))
+                .copied()
+                .collect(),
+            AsepriteDirection::PingPongReverse => reverse
+                .iter()
+                .chain(forward.iter().skip(1).take(forward.len().saturating_sub(2)))
+                .copied()
+                .collect(),
+        }
+    }
+
+    /// Creates one animation per frame tag.
+    /// `AnimatedSprite` only supports a fixed speed per animation. Frames with a longer duration
+    /// are therefore repeated to match the greatest common divisor of all durations in the tag.
+    /// Invalid tags are skipped
+    pub fn animations(&self) -> Vec<AnimationProperties> {
+        self.meta
+            .frame_tags
+            .iter()
+            .filter(|tag| {
+                let valid = self.is_valid_tag(tag);
+                if !valid {
+                    error!(
+                        "{}: Tag {} uses frames {} to {} but the sheet has {} frames",
+                        self.meta.image,
+                        tag.name,
+                        tag.from,
+                        tag.to,
+                        self.frames.0.len()
+                    );
+                }
+                valid
+            })
+            .map(|tag| {
+                let frames = self.tag_frames(tag);
+                let step = frames
+                    .iter()
+                    .map(|idx| self.frames.0[*idx].duration.max(1))
+                    .fold(0, gcd)
+                    .max(1);
+                let indices = frames
+                    .iter()
+                    .flat_map(|idx| {
+                        let repeat = (self.frames.0[*idx].duration.max(1) / step) as usize;
+                        std::iter::repeat(*idx).take(repeat)
+                    })
+                    .collect();
+                AnimationProperties {
+                    name: tag.name.clone(),
+                    indices,
+                    speed: step as f32 / 1000.0,
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,44 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(tag = "type")]
+pub enum BossAttack {
+    /// Jumps up and sends a wave along the ground when landing
+    #[serde(rename = "ground_pound")]
+    GroundPound {
+        jump_velocity: f32,
+        /// Projectile sent along the ground
+        wave: Option<String>,
+    },
+    /// Fires a projectile towards the player
+    #[serde(rename = "throw")]
+    Throw {
+        projectile: String,
+        /// Spawn position relative to the boss
+        #[serde(default)]
+        offset: Vec2,
+    },
+    /// Runs towards the player and returns afterwards
+    #[serde(rename = "charge")]
+    Charge { speed: f32, duration: f32 },
+}
+
+/// What the boss is currently doing
+#[derive(Clone, Copy, Default, Debug)]
+pub enum BossAction {
+    #[default]
+    Idle,
+    /// Moving back to the home position
+    Returning,
+    Charging {
+        speed: f32,
+        remaining: f32,
+    },
+    /// Indices of the ground pound attack
+    Pounding {
+        falling: bool,
+        phase: usize,
+        attack: usize,
+    },
+}
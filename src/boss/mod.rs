@@ -0,0 +1,435 @@
+use attack::{BossAction, BossAttack};
+use bevy::prelude::*;
+use bevy_rapier2d::{dynamics::Velocity, geometry::CollisionGroups};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aseprite::AsepriteSheet,
+    config::GameConfig,
+    entities::{
+        character::CharacterProperties,
+        defeat::{Defeatable, Defeated, Health},
+        enemy::{spawn_enemy, Enemy},
+        projectile::{spawn_projectile, ProjectileOwner, Projectiles},
+    },
+    ground::{surface_height, GroundChunk},
+    model::Models,
+    physics::{CollisionGroup, Hitbox, HorizontalSpeed},
+    AppState, GameState, RunProgress,
+};
+
+pub mod attack;
+
+/// Speed used to walk in and to return after a charge
+const RETURN_SPEED: f32 = 80.0;
+/// Waves are spawned slightly above the surface of the ground so they don't hit it
+const WAVE_HEIGHT: f32 = 1.0;
+
+/// Fires the projectile with the given name from the boss towards the player
+fn fire_projectile(
+    commands: &mut Commands,
+    projectiles: &Projectiles,
+    models: &Models,
+    boss: Entity,
+    name: &str,
+    position: Vec2,
+) {
+    let Some(projectile) = projectiles.projectiles.get(name) else {
+        warn!("Unknown projectile {}", name);
+        return;
+    };
+    spawn_projectile(
+        commands,
+        projectile,
+        models,
+        ProjectileOwner::Enemy,
+        boss,
+        position,
+        Vec2::NEG_X,
+    );
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct BossPhaseProperties {
+    /// The phase starts once the health fraction drops to this value
+    pub health_below: f32,
+    /// Seconds between two attacks
+    pub cooldown: f32,
+    /// Used in order
+    pub attacks: Vec<BossAttack>,
+}
+
+impl Default for BossPhaseProperties {
+    fn default() -> Self {
+        Self {
+            health_below: 1.0,
+            cooldown: 2.0,
+            attacks: vec![],
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct BossProperties {
+    pub name: String,
+    /// Model from the boss characters
+    pub model: String,
+    /// Distance of the run at which the boss appears
+    pub distance: f32,
+    /// Position the boss fights from. Relative to the distance of the run
+    pub home_x: f32,
+    /// The boss leaves after this many seconds. Has to be defeated otherwise
+    pub survive: Option<f32>,
+    /// Sorted by descending `health_below`
+    pub phases: Vec<BossPhaseProperties>,
+}
+
+impl BossProperties {
+    fn phase(&self, health: f32) -> Option<usize> {
+        self.phases
+            .iter()
+            .rposition(|phase| health <= phase.health_below)
+    }
+}
+
+#[derive(Deserialize, Serialize, Asset, TypePath, Resource, Clone, Default)]
+pub struct Bosses {
+    pub bosses: Vec<BossProperties>,
+}
+
+#[derive(Component)]
+pub struct Boss {
+    /// Index into `Bosses`
+    pub index: usize,
+    pub elapsed: f32,
+    pub cooldown: f32,
+    pub next_attack: usize,
+    pub action: BossAction,
+}
+
+/// Boss that wasn't defeated in time and walks off to the right. Harmless from then on
+#[derive(Component)]
+pub struct LeavingBoss;
+
+/// Progress through the bosses of the current run
+#[derive(Resource, Default)]
+pub struct BossEncounter {
+    pub active: Option<Entity>,
+    /// Index of the next boss
+    pub next: usize,
+}
+
+impl BossEncounter {
+    /// Distance of the run from which the ground stays flat for the active or the next boss
+    pub fn arena_start(&self, bosses: &Bosses) -> Option<f32> {
+        if self.active.is_some() {
+            return Some(f32::NEG_INFINITY);
+        }
+        bosses.bosses.get(self.next).map(|boss| boss.distance)
+    }
+}
+
+/// Run condition for everything that pauses during boss fights
+pub fn no_boss_active(encounter: Res<BossEncounter>) -> bool {
+    encounter.active.is_none()
+}
+
+fn start_encounter(
+    mut commands: Commands,
+    mut encounter: ResMut<BossEncounter>,
+    bosses: Res<Bosses>,
+    models: Res<Models>,
+    progress: Res<RunProgress>,
+    config: Res<GameConfig>,
+    q_chunks: Query<&GroundChunk>,
+) {
+    if encounter.active.is_some() {
+        return;
+    }
+    let Some(boss) = bosses.bosses.get(encounter.next) else {
+        return;
+    };
+    if progress.distance < boss.distance {
+        return;
+    }
+    let Some(model) = models.models.get(&boss.model) else {
+        warn!("Unknown boss model {}", boss.model);
+        encounter.next += 1;
+        return;
+    };
+    info!("Boss {} appears", boss.name);
+    // Walks in from the spawn point like any other enemy
+    let x = progress.distance + config.spawn_x;
+    let height = surface_height(&q_chunks, x).unwrap_or_default();
+    let entity = spawn_enemy(&mut commands, &boss.model, model, x, height);
+    commands
+        .entity(entity)
+        .insert(Boss {
+            index: encounter.next,
+            elapsed: 0.0,
+            cooldown: 0.0,
+            next_attack: 0,
+            action: BossAction::Returning,
+        })
+        .insert(Name::new(boss.name.clone()))
+        // The boss keeps pace with the player
+        .remove::<HorizontalSpeed>();
+    encounter.active = Some(entity);
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn update_bosses(
+    mut commands: Commands,
+    time: Res<Time<Virtual>>,
+    bosses: Res<Bosses>,
+    models: Res<Models>,
+    projectiles: Res<Projectiles>,
+    progress: Res<RunProgress>,
+    config: Res<GameConfig>,
+    mut q_bosses: Query<
+        (
+            Entity,
+            &mut Boss,
+            &mut Velocity,
+            &Transform,
+            Option<&Health>,
+            Option<&Children>,
+        ),
+        Without<Defeated>,
+    >,
+    q_hitboxes: Query<(), With<Hitbox>>,
+    q_chunks: Query<&GroundChunk>,
+) {
+    for (entity, mut boss, mut velocity, transform, health, children) in &mut q_bosses {
+        let props = &bosses.bosses[boss.index];
+        boss.elapsed += time.delta_seconds();
+        if props.survive.is_some_and(|survive| boss.elapsed >= survive) {
+            info!("Boss {} leaves", props.name);
+            commands
+                .entity(entity)
+                .remove::<(Boss, Enemy, Defeatable)>()
+                .insert(LeavingBoss);
+            // Only the ground is left to collide with
+            for child in children.into_iter().flatten() {
+                if q_hitboxes.contains(*child) {
+                    commands.entity(*child).insert(CollisionGroups::new(
+                        CollisionGroup::Enemy.group(),
+                        CollisionGroup::Wall.group(),
+                    ));
+                }
+            }
+            continue;
+        }
+        let position = transform.translation.xy();
+        // Every action other than charging keeps pace with the player
+        velocity.linvel.x = config.scroll_speed;
+        let action = boss.action;
+        boss.action = match action {
+            BossAction::Idle => {
+                boss.cooldown -= time.delta_seconds();
+                let phase_idx = props.phase(health.map(Health::fraction).unwrap_or(1.0));
+                match phase_idx.map(|idx| (idx, &props.phases[idx])) {
+                    Some((phase_idx, phase))
+                        if boss.cooldown <= 0.0 && !phase.attacks.is_empty() =>
+                    {
+                        let attack_idx = boss.next_attack % phase.attacks.len();
+                        boss.next_attack += 1;
+                        boss.cooldown = phase.cooldown;
+                        match &phase.attacks[attack_idx] {
+                            BossAttack::GroundPound { jump_velocity, .. } => {
+                                velocity.linvel.y = *jump_velocity;
+                                BossAction::Pounding {
+                                    falling: false,
+                                    phase: phase_idx,
+                                    attack: attack_idx,
+                                }
+                            }
+                            BossAttack::Throw { projectile, offset } => {
+                                fire_projectile(
+                                    &mut commands,
+                                    &projectiles,
+                                    &models,
+                                    entity,
+                                    projectile,
+                                    position + *offset,
+                                );
+                                BossAction::Idle
+                            }
+                            BossAttack::Charge { speed, duration } => BossAction::Charging {
+                                speed: *speed,
+                                remaining: *duration,
+                            },
+                        }
+                    }
+                    _ => BossAction::Idle,
+                }
+            }
+            BossAction::Charging { speed, remaining } => {
+                velocity.linvel.x = config.scroll_speed - speed;
+                let remaining = remaining - time.delta_seconds();
+                if remaining <= 0.0 {
+                    BossAction::Returning
+                } else {
+                    BossAction::Charging { speed, remaining }
+                }
+            }
+            BossAction::Returning => {
+                let offset = progress.distance + props.home_x - position.x;
+                if offset.abs() < 2.0 {
+                    BossAction::Idle
+                } else {
+                    // Don't overshoot the home position on long frames
+                    let speed =
+                        (offset.abs() / time.delta_seconds().max(f32::EPSILON)).min(RETURN_SPEED);
+                    velocity.linvel.x += offset.signum() * speed;
+                    BossAction::Returning
+                }
+            }
+            BossAction::Pounding {
+                falling,
+                phase,
+                attack,
+            } => {
+                if !falling {
+                    BossAction::Pounding {
+                        falling: velocity.linvel.y < 0.0,
+                        phase,
+                        attack,
+                    }
+                } else if velocity.linvel.y.abs() < 1.0 {
+                    // Landed
+                    if let BossAttack::GroundPound {
+                        wave: Some(wave), ..
+                    } = &props.phases[phase].attacks[attack]
+                    {
+                        fire_projectile(
+                            &mut commands,
+                            &projectiles,
+                            &models,
+                            entity,
+                            wave,
+                            Vec2::new(
+                                position.x,
+                                surface_height(&q_chunks, position.x).unwrap_or_default()
+                                    + WAVE_HEIGHT,
+                            ),
+                        );
+                    }
+                    BossAction::Idle
+                } else {
+                    BossAction::Pounding {
+                        falling,
+                        phase,
+                        attack,
+                    }
+                }
+            }
+        };
+    }
+}
+
+/// Walks off the screen ahead of the run and disappears
+fn leave_bosses(
+    mut commands: Commands,
+    progress: Res<RunProgress>,
+    config: Res<GameConfig>,
+    mut q_leaving: Query<(Entity, &mut Velocity, &Transform), With<LeavingBoss>>,
+) {
+    for (entity, mut velocity, transform) in &mut q_leaving {
+        velocity.linvel.x = config.scroll_speed + RETURN_SPEED;
+        if transform.translation.x > progress.distance + config.spawn_x {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Continues with the regular spawning once the boss is gone
+fn finish_encounter(
+    mut encounter: ResMut<BossEncounter>,
+    q_bosses: Query<(), (With<Boss>, Without<Defeated>)>,
+) {
+    if let Some(active) = encounter.active {
+        if !q_bosses.contains(active) {
+            encounter.active = None;
+            encounter.next += 1;
+        }
+    }
+}
+
+fn reset_encounter(mut commands: Commands, q_leaving: Query<Entity, With<LeavingBoss>>) {
+    for entity in &q_leaving {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.insert_resource(BossEncounter::default());
+}
+
+#[derive(Resource, Default)]
+pub struct BossHandles {
+    bosses: Handle<Bosses>,
+    characters: Handle<CharacterProperties>,
+}
+
+fn load_assets(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(BossHandles {
+        bosses: asset_server.load("bosses.yaml"),
+        characters: asset_server.load("bosses.characters.yaml"),
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn setup_bosses(
+    mut commands: Commands,
+    mut models: ResMut<Models>,
+    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+    asset_server: Res<AssetServer>,
+    mut character_assets: ResMut<Assets<CharacterProperties>>,
+    mut boss_assets: ResMut<Assets<Bosses>>,
+    aseprite_sheets: Res<Assets<AsepriteSheet>>,
+    handles: Res<BossHandles>,
+) {
+    if let Some(bosses) = boss_assets.remove(handles.bosses.id()) {
+        info!("Loaded {} bosses", bosses.bosses.len());
+        commands.insert_resource(bosses);
+    }
+    if let Some(characters) = CharacterProperties::take_loaded(
+        &mut character_assets,
+        &handles.characters,
+        &asset_server,
+        &aseprite_sheets,
+    ) {
+        for character in &characters.characters {
+            if let Some(model) =
+                character.get_model(&asset_server, &mut texture_atlas_layouts, &aseprite_sheets)
+            {
+                models.models.insert(character.name.clone(), model);
+            }
+        }
+    }
+}
+
+pub struct BossPlugin;
+
+impl Plugin for BossPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_assets)
+            .add_systems(Update, setup_bosses)
+            .add_systems(
+                Update,
+                (
+                    start_encounter,
+                    update_bosses,
+                    leave_bosses,
+                    finish_encounter,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Running))
+                    .run_if(in_state(AppState::Game)),
+            )
+            .add_systems(OnEnter(AppState::Game), reset_encounter)
+            .init_resource::<BossHandles>()
+            .init_resource::<BossEncounter>()
+            .init_resource::<Bosses>();
+    }
+}
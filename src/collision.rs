@@ -0,0 +1,85 @@
+use std::marker::PhantomData;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_rapier2d::pipeline::CollisionEvent;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ContactPhase {
+    Started,
+    Stopped,
+}
+
+/// Contact between a collider belonging to an entity with `A` and a collider belonging to an
+/// entity with `B`. Registered with `ContactAppExt::add_contact_pair`
+#[derive(Event)]
+pub struct Contact<A: Component, B: Component> {
+    pub a: Entity,
+    pub b: Entity,
+    /// Collider that touched `b`. Either `a` itself or one of its descendants
+    pub a_collider: Entity,
+    /// Collider that touched `a`. Either `b` itself or one of its descendants
+    pub b_collider: Entity,
+    pub phase: ContactPhase,
+    marker: PhantomData<fn() -> (A, B)>,
+}
+
+impl<A: Component, B: Component> Contact<A, B> {
+    pub fn started(&self) -> bool {
+        self.phase == ContactPhase::Started
+    }
+}
+
+/// Resolves colliders to the entity they belong to
+#[derive(SystemParam)]
+pub struct ColliderOwner<'w, 's, T: Component> {
+    q_owners: Query<'w, 's, (), With<T>>,
+    q_parents: Query<'w, 's, &'static Parent>,
+}
+
+impl<T: Component> ColliderOwner<'_, '_, T> {
+    /// The collider itself or its closest ancestor with `T`
+    pub fn get(&self, collider: Entity) -> Option<Entity> {
+        std::iter::once(collider)
+            .chain(self.q_parents.iter_ancestors(collider))
+            .find(|ancestor| self.q_owners.contains(*ancestor))
+    }
+}
+
+fn dispatch_contacts<A: Component, B: Component>(
+    mut er_collision: EventReader<CollisionEvent>,
+    owner_a: ColliderOwner<A>,
+    owner_b: ColliderOwner<B>,
+    mut ew_contact: EventWriter<Contact<A, B>>,
+) {
+    for event in er_collision.read() {
+        let (ent1, ent2, phase) = match event {
+            CollisionEvent::Started(ent1, ent2, _flags) => (*ent1, *ent2, ContactPhase::Started),
+            CollisionEvent::Stopped(ent1, ent2, _flags) => (*ent1, *ent2, ContactPhase::Stopped),
+        };
+        // Both sides can match if they share a component
+        for (a_collider, b_collider) in [(ent1, ent2), (ent2, ent1)] {
+            if let (Some(a), Some(b)) = (owner_a.get(a_collider), owner_b.get(b_collider)) {
+                ew_contact.send(Contact {
+                    a,
+                    b,
+                    a_collider,
+                    b_collider,
+                    phase,
+                    marker: PhantomData,
+                });
+            }
+        }
+    }
+}
+
+pub trait ContactAppExt {
+    /// Sends a `Contact<A, B>` for every collision between entities with `A` and `B`
+    fn add_contact_pair<A: Component, B: Component>(&mut self) -> &mut Self;
+}
+
+impl ContactAppExt for App {
+    fn add_contact_pair<A: Component, B: Component>(&mut self) -> &mut Self {
+        self.add_event::<Contact<A, B>>()
+            .add_systems(PreUpdate, dispatch_contacts::<A, B>)
+    }
+}
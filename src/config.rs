@@ -0,0 +1,82 @@
+use bevy::prelude::*;
+use bevy_rapier2d::plugin::RapierConfiguration;
+use serde::{Deserialize, Serialize};
+
+use crate::{Despawner, FollowCamera};
+
+/// Gameplay values tuned without touching the code. Reloaded whenever the file changes if the
+/// game is built with the `file_watcher` feature
+#[derive(Deserialize, Serialize, Asset, TypePath, Resource, Clone)]
+#[serde(default)]
+pub struct GameConfig {
+    pub gravity: Vec2,
+    /// Upwards velocity at the start of a jump. Characters can override it
+    pub jump_velocity: f32,
+    /// Speed at which the camera moves through the world
+    pub scroll_speed: f32,
+    /// Enemies are spawned this far ahead of the run
+    pub spawn_x: f32,
+    /// Enemies are removed this far behind the camera
+    pub despawner_x: f32,
+    pub camera_scale: f32,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            gravity: Vec2::new(0.0, -800.0),
+            jump_velocity: 300.0,
+            scroll_speed: 100.0,
+            spawn_x: 500.0,
+            despawner_x: -500.0,
+            camera_scale: 0.25,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ConfigHandle(Handle<GameConfig>);
+
+fn load_config(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(ConfigHandle(asset_server.load("game.config.yaml")));
+}
+
+fn apply_config(
+    mut commands: Commands,
+    mut er_asset: EventReader<AssetEvent<GameConfig>>,
+    config_assets: Res<Assets<GameConfig>>,
+    handle: Res<ConfigHandle>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+    mut q_projections: Query<&mut OrthographicProjection>,
+    mut q_despawner: Query<&mut FollowCamera, With<Despawner>>,
+) {
+    for event in er_asset.read() {
+        if !event.is_loaded_with_dependencies(handle.0.id()) && !event.is_modified(handle.0.id()) {
+            continue;
+        }
+        let Some(config) = config_assets.get(handle.0.id()) else {
+            continue;
+        };
+        info!("Applying game config");
+        rapier_config.gravity = config.gravity;
+        // Player and parallax camera
+        for mut projection in &mut q_projections {
+            projection.scale = config.camera_scale;
+        }
+        for mut follow in &mut q_despawner {
+            follow.offset = config.despawner_x;
+        }
+        commands.insert_resource(config.clone());
+    }
+}
+
+pub struct ConfigPlugin;
+
+impl Plugin for ConfigPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_config)
+            .add_systems(Update, apply_config)
+            .init_resource::<GameConfig>()
+            .init_resource::<ConfigHandle>();
+    }
+}
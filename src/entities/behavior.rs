@@ -0,0 +1,173 @@
+use std::f32::consts::TAU;
+
+use bevy::{ecs::system::EntityCommands, prelude::*};
+use bevy_rapier2d::{
+    dynamics::{GravityScale, Velocity},
+    geometry::CollisionGroups,
+    pipeline::QueryFilter,
+    plugin::RapierContext,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    physics::{CollisionGroup, HorizontalSpeed},
+    GameState,
+};
+
+use super::player::{GroundCheck, Player};
+
+/// Jumps periodically while resting on the ground
+#[derive(Component, Deserialize, Serialize, Clone, Default)]
+pub struct Hopper {
+    /// Seconds between jumps
+    pub interval: f32,
+    pub jump_velocity: f32,
+    /// Only hops while one of the probes finds ground
+    #[serde(default)]
+    pub ground_check: GroundCheck,
+    #[serde(skip)]
+    pub elapsed: f32,
+}
+
+/// Flies in a sine wave. Disables gravity of the body
+#[derive(Component, Deserialize, Serialize, Clone, Default)]
+pub struct SineFlyer {
+    pub amplitude: f32,
+    /// Waves per second
+    pub frequency: f32,
+    #[serde(skip)]
+    pub elapsed: f32,
+}
+
+/// Accelerates towards the player once it is close enough
+#[derive(Component, Deserialize, Serialize, Clone, Default)]
+pub struct Charger {
+    pub trigger_distance: f32,
+    pub acceleration: f32,
+    pub max_speed: f32,
+}
+
+/// Walks back and forth
+#[derive(Component, Deserialize, Serialize, Clone, Default)]
+pub struct Patroller {
+    /// Distance before turning around
+    pub distance: f32,
+    pub speed: f32,
+    #[serde(skip)]
+    pub travelled: f32,
+    #[serde(skip)]
+    pub turned: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(tag = "type")]
+pub enum BehaviorProperties {
+    #[serde(rename = "hopper")]
+    Hopper(Hopper),
+    #[serde(rename = "sine_flyer")]
+    SineFlyer(SineFlyer),
+    #[serde(rename = "charger")]
+    Charger(Charger),
+    #[serde(rename = "patroller")]
+    Patroller(Patroller),
+}
+
+impl BehaviorProperties {
+    pub fn insert(&self, commands: &mut EntityCommands) {
+        match self {
+            Self::Hopper(hopper) => {
+                commands.insert(hopper.clone());
+            }
+            Self::SineFlyer(flyer) => {
+                commands.insert(flyer.clone()).insert(GravityScale(0.0));
+            }
+            // Both control the horizontal velocity on their own
+            Self::Charger(charger) => {
+                commands.insert(charger.clone()).remove::<HorizontalSpeed>();
+            }
+            Self::Patroller(patroller) => {
+                commands
+                    .insert(patroller.clone())
+                    .remove::<HorizontalSpeed>();
+            }
+        }
+    }
+}
+
+fn hop(
+    time: Res<Time<Virtual>>,
+    rapier_context: Res<RapierContext>,
+    mut q_hoppers: Query<(&mut Hopper, &mut Velocity, &Transform)>,
+) {
+    let filter = QueryFilter::new().groups(CollisionGroups::new(
+        CollisionGroup::Enemy.group(),
+        CollisionGroup::Wall.group(),
+    ));
+    for (mut hopper, mut velocity, transform) in &mut q_hoppers {
+        hopper.elapsed += time.delta_seconds();
+        if hopper.elapsed < hopper.interval || velocity.linvel.y > 0.0 {
+            continue;
+        }
+        let grounded = hopper
+            .ground_check
+            .find_ground(&rapier_context, transform, filter)
+            .is_some();
+        if grounded {
+            hopper.elapsed = 0.0;
+            velocity.linvel.y = hopper.jump_velocity;
+        }
+    }
+}
+
+fn fly(time: Res<Time<Virtual>>, mut q_flyers: Query<(&mut SineFlyer, &mut Velocity)>) {
+    for (mut flyer, mut velocity) in &mut q_flyers {
+        flyer.elapsed += time.delta_seconds();
+        // Derivative of amplitude * sin(TAU * frequency * t)
+        let omega = TAU * flyer.frequency;
+        velocity.linvel.y = flyer.amplitude * omega * (omega * flyer.elapsed).cos();
+    }
+}
+
+fn charge(
+    time: Res<Time<Virtual>>,
+    mut q_chargers: Query<(&Charger, &Transform, &mut Velocity)>,
+    q_player: Query<&Transform, With<Player>>,
+) {
+    let Ok(player_transform) = q_player.get_single() else {
+        return;
+    };
+    for (charger, transform, mut velocity) in &mut q_chargers {
+        let distance = transform.translation.x - player_transform.translation.x;
+        if distance > 0.0 && distance < charger.trigger_distance {
+            velocity.linvel.x = (velocity.linvel.x - charger.acceleration * time.delta_seconds())
+                .max(-charger.max_speed);
+        }
+    }
+}
+
+fn patrol(
+    time: Res<Time<Virtual>>,
+    mut q_patrollers: Query<(&mut Patroller, &mut Velocity, &mut Sprite)>,
+) {
+    for (mut patroller, mut velocity, mut sprite) in &mut q_patrollers {
+        patroller.travelled += patroller.speed * time.delta_seconds();
+        if patroller.travelled >= patroller.distance {
+            patroller.travelled = 0.0;
+            patroller.turned = !patroller.turned;
+            sprite.flip_x = !sprite.flip_x;
+        }
+        let direction = if patroller.turned { 1.0 } else { -1.0 };
+        velocity.linvel.x = patroller.speed * direction;
+    }
+}
+
+pub struct BehaviorPlugin;
+
+impl Plugin for BehaviorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (hop, fly, charge, patrol).run_if(in_state(GameState::Running)),
+        );
+    }
+}
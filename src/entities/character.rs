@@ -1,11 +1,25 @@
-use bevy::prelude::*;
+use bevy::{ecs::system::EntityCommands, prelude::*, sprite::Anchor};
 use bevy_2d_animations::{Animated, AnimatedSprite};
-use bevy_rapier2d::geometry::Collider;
+use bevy_rapier2d::{
+    dynamics::{AdditionalMassProperties, GravityScale, RigidBody, Velocity},
+    geometry::{Collider, CollisionGroups},
+};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 use crate::{
-    model::Model,
-    physics::{ColliderChild, ControllerBundle},
+    aseprite::AsepriteSheet,
+    model::{FrameAnchors, FrameEvent, FrameEvents, FrameHitboxes, Model, IDLE_ANIMATION},
+    physics::{ColliderChild, CollisionGroupsProperties, ControllerBundle, HorizontalSpeed},
+    spawner::SpawnProperties,
+};
+
+use super::{
+    behavior::BehaviorProperties,
+    defeat::DefeatProperties,
+    player::{AirProperties, DashProperties, GroundCheck, JumpProperties},
+    projectile::WeaponProperties,
+    state_machine::AnimationStateMachine,
 };
 
 #[derive(Component, Default)]
@@ -13,61 +27,167 @@ pub struct Character;
 
 #[derive(Bundle, Default)]
 pub struct CharacterBundle {
-    pub animation_state: CharacterAnimationState,
-
     pub controller: ControllerBundle,
 
     pub character: Character,
 }
 
-#[derive(Component, Default, Hash, PartialEq, Eq, Debug)]
-pub enum CharacterAnimationState {
-    #[default]
-    Running,
-    Jumping,
-    Ducking,
-}
-
 #[derive(Deserialize, Serialize, Clone)]
 pub struct CapsuleColliderY {
     pub radius: f32,
     pub height: f32,
 }
 
+#[derive(Deserialize, Serialize, Clone)]
+pub struct CuboidCollider {
+    pub half_width: f32,
+    pub half_height: f32,
+}
+
 #[derive(Deserialize, Serialize, Clone)]
 #[serde(tag = "type")]
 pub enum ColliderType {
     #[serde(rename = "capsule_y")]
     CapsuleY(CapsuleColliderY),
+    #[serde(rename = "cuboid")]
+    Cuboid(CuboidCollider),
 }
 
 impl From<ColliderType> for Collider {
     fn from(value: ColliderType) -> Self {
         match value {
             ColliderType::CapsuleY(data) => Collider::capsule_y(data.height, data.radius),
+            ColliderType::Cuboid(data) => Collider::cuboid(data.half_width, data.half_height),
         }
     }
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct ColliderProperties {
     pub collider: ColliderType,
     #[serde(default)]
     pub translation: Vec2,
 }
 
-#[derive(Deserialize, Serialize, Default)]
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct PhysicsProperties {
+    pub gravity_scale: f32,
+    /// Mass added to the mass computed from the colliders
+    pub mass: Option<f32>,
+    /// Horizontal speed towards the player on top of the scrolling. Keeps the body moving
+    /// at this speed
+    pub speed: Option<f32>,
+    pub initial_velocity: Vec2,
+    /// Kinematic bodies ignore gravity and forces and only move by their velocity
+    pub kinematic: bool,
+}
+
+impl Default for PhysicsProperties {
+    fn default() -> Self {
+        Self {
+            gravity_scale: 1.0,
+            mass: None,
+            speed: None,
+            initial_velocity: Vec2::ZERO,
+            kinematic: false,
+        }
+    }
+}
+
+impl PhysicsProperties {
+    pub fn insert(&self, commands: &mut EntityCommands) {
+        let mut velocity = self.initial_velocity;
+        if let Some(speed) = self.speed {
+            velocity.x = -speed;
+            commands.insert(HorizontalSpeed(speed));
+        }
+        commands
+            .insert(GravityScale(self.gravity_scale))
+            .insert(Velocity::linear(velocity));
+        if let Some(mass) = self.mass {
+            commands.insert(AdditionalMassProperties::Mass(mass));
+        }
+        if self.kinematic {
+            commands.insert(RigidBody::KinematicVelocityBased);
+        }
+    }
+}
+
+/// A single frame of a sprite sheet with non uniform tiles
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct FrameProperties {
+    /// Top left corner of the frame in the image
+    pub position: Vec2,
+    pub size: Vec2,
+    /// Pixel of the frame that is placed at the origin of the body. Measured from the top left
+    /// corner of the frame. Defaults to the pivot of the character
+    pub pivot: Option<Vec2>,
+}
+
+impl From<&ColliderProperties> for ColliderChild {
+    fn from(value: &ColliderProperties) -> Self {
+        ColliderChild {
+            collider: value.collider.clone().into(),
+            transform: TransformBundle {
+                local: Transform::from_translation(value.translation.extend(0.0)),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// Colliders used while one of the given frames of the animation is shown
+#[derive(Deserialize, Serialize, Default, Clone)]
+pub struct FrameColliderProperties {
+    /// Indices into the sprite sheet
+    pub frames: Vec<usize>,
+    pub colliders: Vec<ColliderProperties>,
+}
+
+/// Emits an `AnimationEvent` when the animation reaches one of the frames
+#[derive(Deserialize, Serialize, Default, Clone)]
+pub struct AnimationEventProperties {
+    pub name: String,
+    /// Position in the animation starting at 0. Consecutive repeated indices count as one frame
+    pub frames: Vec<usize>,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone)]
+#[serde(default)]
 pub struct AnimationProperties {
     pub name: String,
     // TODO: allow for range in yaml
+    /// Can be left empty to add settings to an aseprite tag with the same name
     pub indices: Vec<usize>,
     pub speed: f32,
+    /// Replaces the colliders of the character while this animation is playing
+    pub colliders: Option<Vec<ColliderProperties>>,
+    /// Replaces the colliders of the character for single frames of this animation
+    pub frame_colliders: Vec<FrameColliderProperties>,
+    pub events: Vec<AnimationEventProperties>,
+}
+
+impl AnimationProperties {
+    /// Indices of the sprite sheet without consecutive repetitions
+    fn frames(&self) -> Vec<usize> {
+        let mut frames = self.indices.clone();
+        frames.dedup();
+        frames
+    }
 }
 
 #[derive(Deserialize, Serialize, Asset, TypePath, Default)]
 #[serde(default)]
 pub struct CharacterProperty {
     pub name: String,
+    /// Path to an Aseprite json export. If set the atlas layout and animations are created from
+    /// the frames and frame tags of the export. `file_path` can be left empty to use the image
+    /// referenced by the export
+    aseprite: Option<String>,
+    #[serde(skip)]
+    aseprite_handle: Option<Handle<AsepriteSheet>>,
     file_path: String,
     tile_size: Vec2,
     rows: usize,
@@ -75,8 +195,35 @@ pub struct CharacterProperty {
     padding: Vec2,
     offset_tiles: Vec2,
     offset_fixed: Vec2,
+    /// Explicit frames for sprite sheets with differently sized tiles. Replaces the grid fields
+    frames: Vec<FrameProperties>,
+    /// Size of the whole image. Only used with `frames`. Defaults to the area covered by the
+    /// frames
+    texture_size: Option<Vec2>,
+    /// Default pivot of all frames. Measured from the top left corner of the frame. The center of
+    /// the frame is used if unset
+    pivot: Option<Vec2>,
     flip_x: bool,
     animations: Vec<AnimationProperties>,
+    /// Selects the animation depending on the movement of the character
+    state_machine: Option<AnimationStateMachine>,
+    physics: Option<PhysicsProperties>,
+    behavior: Option<BehaviorProperties>,
+    /// Allows the player to defeat the enemy instead of getting hurt
+    defeat: Option<DefeatProperties>,
+    weapon: Option<WeaponProperties>,
+    /// Overrides the default collision groups of players or enemies
+    collision_groups: Option<CollisionGroupsProperties>,
+    /// Only used for the player. Defaults to a single ray from the center of the body
+    ground_check: Option<GroundCheck>,
+    /// Only used for the player
+    jump: Option<JumpProperties>,
+    /// Only used for the player
+    air: Option<AirProperties>,
+    /// Only used for the player
+    dash: Option<DashProperties>,
+    /// Only used for enemies
+    pub spawn: SpawnProperties,
     colliders: Vec<ColliderProperties>,
 }
 
@@ -85,28 +232,271 @@ pub struct CharacterProperties {
     pub characters: Vec<CharacterProperty>,
 }
 
+/// Assets made of one or more characters
+pub trait CharacterAsset: Asset {
+    fn characters_mut(&mut self) -> &mut [CharacterProperty];
+}
+
+impl CharacterAsset for CharacterProperties {
+    fn characters_mut(&mut self) -> &mut [CharacterProperty] {
+        &mut self.characters
+    }
+}
+
+impl CharacterAsset for CharacterProperty {
+    fn characters_mut(&mut self) -> &mut [CharacterProperty] {
+        std::slice::from_mut(self)
+    }
+}
+
+impl CharacterProperties {
+    /// Removes the asset once all its characters and the assets they reference are loaded.
+    /// Starts loading the referenced assets until then
+    pub fn take_loaded<T: CharacterAsset>(
+        assets: &mut Assets<T>,
+        handle: &Handle<T>,
+        asset_server: &AssetServer,
+        aseprite_sheets: &Assets<AsepriteSheet>,
+    ) -> Option<T> {
+        let mut loaded = true;
+        for character in assets.get_mut(handle)?.characters_mut() {
+            character.load_dependencies(asset_server);
+            loaded &= character.dependencies_loaded(aseprite_sheets);
+        }
+        if loaded {
+            assets.remove(handle)
+        } else {
+            None
+        }
+    }
+}
+
 impl CharacterProperty {
+    fn frame_anchors(&self, layout: &TextureAtlasLayout) -> Option<FrameAnchors> {
+        let has_pivot =
+            self.pivot.is_some() || self.frames.iter().any(|frame| frame.pivot.is_some());
+        if !has_pivot {
+            return None;
+        }
+        let anchors = layout
+            .textures
+            .iter()
+            .enumerate()
+            .map(|(idx, rect)| {
+                let pivot = self
+                    .frames
+                    .get(idx)
+                    .and_then(|frame| frame.pivot)
+                    .or(self.pivot);
+                match pivot {
+                    // Anchors are relative to the center with y pointing up
+                    Some(pivot) => Anchor::Custom(Vec2::new(
+                        pivot.x / rect.width() - 0.5,
+                        0.5 - pivot.y / rect.height(),
+                    )),
+                    None => Anchor::Center,
+                }
+            })
+            .collect();
+        Some(FrameAnchors(anchors))
+    }
+
+    fn frame_hitboxes(&self, animations: &[AnimationProperties]) -> Option<FrameHitboxes> {
+        let mut hitboxes = FrameHitboxes {
+            sets: vec![self.colliders.iter().map(ColliderChild::from).collect()],
+            ..Default::default()
+        };
+        for anim in animations {
+            if let Some(colliders) = &anim.colliders {
+                hitboxes
+                    .sets
+                    .push(colliders.iter().map(ColliderChild::from).collect());
+                hitboxes
+                    .animation_sets
+                    .insert(anim.name.clone(), hitboxes.sets.len() - 1);
+            }
+            for frame_colliders in &anim.frame_colliders {
+                hitboxes.sets.push(
+                    frame_colliders
+                        .colliders
+                        .iter()
+                        .map(ColliderChild::from)
+                        .collect(),
+                );
+                for idx in &frame_colliders.frames {
+                    hitboxes
+                        .frame_sets
+                        .insert((anim.name.clone(), *idx), hitboxes.sets.len() - 1);
+                }
+            }
+        }
+        if hitboxes.animation_sets.is_empty() && hitboxes.frame_sets.is_empty() {
+            None
+        } else {
+            Some(hitboxes)
+        }
+    }
+
+    fn frame_events(&self, animations: &[AnimationProperties]) -> Option<FrameEvents> {
+        let mut events = vec![];
+        let mut animation_frames = HashMap::new();
+        for anim in animations.iter().filter(|anim| !anim.events.is_empty()) {
+            let frames = anim.frames();
+            for event in &anim.events {
+                for frame in &event.frames {
+                    match frames.get(*frame) {
+                        Some(_) => events.push(FrameEvent {
+                            animation: anim.name.clone(),
+                            position: *frame,
+                            name: event.name.clone(),
+                        }),
+                        None => warn!(
+                            "{}: Event {} uses frame {} but {} only has {} frames",
+                            self.name,
+                            event.name,
+                            frame,
+                            anim.name,
+                            frames.len()
+                        ),
+                    }
+                }
+            }
+            animation_frames.insert(anim.name.clone(), frames);
+        }
+        if events.is_empty() {
+            None
+        } else {
+            Some(FrameEvents {
+                events,
+                frames: animation_frames,
+                ..Default::default()
+            })
+        }
+    }
+
+    /// Starts loading the assets referenced by this character
+    fn load_dependencies(&mut self, asset_server: &AssetServer) {
+        if let (Some(path), None) = (&self.aseprite, &self.aseprite_handle) {
+            self.aseprite_handle = Some(asset_server.load(path.clone()));
+        }
+    }
+
+    /// Size of the image for `frames`. `None` if a frame is empty or doesn't fit into the image
+    fn frames_texture_size(&self) -> Option<Vec2> {
+        let covered = self.frames.iter().fold(Vec2::ZERO, |size, frame| {
+            size.max(frame.position + frame.size)
+        });
+        let texture_size = self.texture_size.unwrap_or(covered);
+        for (idx, frame) in self.frames.iter().enumerate() {
+            if frame.size.x <= 0.0 || frame.size.y <= 0.0 {
+                error!("{}: Frame {} has no size", self.name, idx);
+                return None;
+            }
+            if frame.position.min_element() < 0.0
+                || (frame.position + frame.size).cmpgt(texture_size).any()
+            {
+                error!(
+                    "{}: Frame {} is outside of the texture of size {}",
+                    self.name, idx, texture_size
+                );
+                return None;
+            }
+        }
+        Some(texture_size)
+    }
+
+    /// Whether all referenced assets are loaded and `get_model` can be called
+    fn dependencies_loaded(&self, aseprite_sheets: &Assets<AsepriteSheet>) -> bool {
+        match &self.aseprite_handle {
+            Some(handle) => aseprite_sheets.contains(handle),
+            None => self.aseprite.is_none(),
+        }
+    }
+
+    /// Builds the model of the character. Logs an error and returns `None` if the definition is
+    /// invalid
     pub fn get_model(
         &self,
         asset_server: &Res<AssetServer>,
         texture_atlas_layouts: &mut ResMut<Assets<TextureAtlasLayout>>,
-    ) -> Model {
-        let idle_set = asset_server.load(self.file_path.clone());
-        let layout = TextureAtlasLayout::from_grid(
-            self.tile_size,
-            self.columns,
-            self.rows,
-            Some(self.padding),
-            Some(self.offset_fixed + self.offset_tiles * self.tile_size),
-        );
+        aseprite_sheets: &Res<Assets<AsepriteSheet>>,
+    ) -> Option<Model> {
+        let aseprite_sheet = self
+            .aseprite_handle
+            .as_ref()
+            .and_then(|handle| aseprite_sheets.get(handle));
+        let (image_path, layout, mut animations) = match (aseprite_sheet, &self.aseprite) {
+            (Some(sheet), Some(json_path)) => {
+                let image_path = if self.file_path.is_empty() {
+                    sheet.image_path(json_path)
+                } else {
+                    self.file_path.clone()
+                };
+                (image_path, sheet.layout(), sheet.animations())
+            }
+            _ if !self.frames.is_empty() => {
+                let mut layout = TextureAtlasLayout::new_empty(self.frames_texture_size()?);
+                for frame in &self.frames {
+                    layout.add_texture(Rect::from_corners(
+                        frame.position,
+                        frame.position + frame.size,
+                    ));
+                }
+                (self.file_path.clone(), layout, vec![])
+            }
+            _ => (
+                self.file_path.clone(),
+                TextureAtlasLayout::from_grid(
+                    self.tile_size,
+                    self.columns,
+                    self.rows,
+                    Some(self.padding),
+                    Some(self.offset_fixed + self.offset_tiles * self.tile_size),
+                ),
+                vec![],
+            ),
+        };
+        // Animations in the yaml replace aseprite tags with the same name. Entries without
+        // indices only add their settings to the tag
+        for anim in &self.animations {
+            match animations.iter_mut().find(|tag| tag.name == anim.name) {
+                Some(tag) if anim.indices.is_empty() => {
+                    let (indices, speed) = (std::mem::take(&mut tag.indices), tag.speed);
+                    *tag = AnimationProperties {
+                        indices,
+                        speed,
+                        ..anim.clone()
+                    };
+                }
+                Some(tag) => *tag = anim.clone(),
+                None => animations.push(anim.clone()),
+            }
+        }
+        let hitboxes = self.frame_hitboxes(&animations);
+        let events = self.frame_events(&animations);
+
+        let anchors = self.frame_anchors(&layout);
+
+        let idle_set = asset_server.load(image_path);
         let texture_atlas_layout = texture_atlas_layouts.add(layout);
         let mut animation = AnimatedSprite::default();
-        for anim_data in &self.animations {
+        for anim_data in &animations {
             animation.add_animation(&anim_data.name, anim_data.indices.clone(), anim_data.speed);
         }
-        animation.queue_animation("idle", true, None);
+        animation.queue_animation(IDLE_ANIMATION, true, None);
         let model = Model {
             animation,
+            anchors,
+            state_machine: self.state_machine.clone(),
+            physics: self.physics.clone(),
+            behavior: self.behavior.clone(),
+            defeat: self.defeat.clone(),
+            weapon: self.weapon.clone(),
+            collision_groups: self.collision_groups.as_ref().map(CollisionGroups::from),
+            ground_check: self.ground_check.clone(),
+            jump: self.jump.clone(),
+            air: self.air.clone(),
+            dash: self.dash.clone(),
             spritesheet: SpriteSheetBundle {
                 texture: idle_set,
                 atlas: TextureAtlas {
@@ -120,19 +510,10 @@ impl CharacterProperty {
                 transform: Transform::from_translation(Vec3::new(0.0, 16.0, 0.0)),
                 ..Default::default()
             },
-            colliders: self
-                .colliders
-                .iter()
-                .map(|collider_props| ColliderChild {
-                    collider: collider_props.collider.clone().into(),
-                    transform: TransformBundle {
-                        local: Transform::from_translation(collider_props.translation.extend(0.0)),
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                })
-                .collect(),
+            colliders: self.colliders.iter().map(ColliderChild::from).collect(),
+            hitboxes,
+            events,
         };
-        model
+        Some(model)
     }
 }
@@ -0,0 +1,211 @@
+use bevy::prelude::*;
+use bevy_2d_animations::{Animated, AnimatedSprite};
+use bevy_rapier2d::{dynamics::RigidBody, dynamics::Velocity, plugin::RapierContext};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    model::FrameHitboxes,
+    physics::{Hitbox, HorizontalSpeed},
+    GameState, RunProgress,
+};
+
+use super::{
+    behavior::{Charger, Hopper, Patroller, SineFlyer},
+    projectile::Weapon,
+    state_machine::AnimationStateMachine,
+};
+
+/// Minimal upwards component of the contact normal to count as landing on top
+const STOMP_NORMAL: f32 = 0.7;
+
+/// How an enemy can be defeated instead of hurting the player
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct DefeatProperties {
+    /// Defeated by landing on top of it
+    pub stomp: bool,
+    /// Defeated by dashing into it
+    pub dash: bool,
+    /// Defeated by projectiles of the player
+    pub projectile: bool,
+    pub score: u32,
+    /// Number of stomps or dashes needed
+    pub health: u32,
+    /// Upwards velocity of the player after a stomp
+    pub bounce_velocity: f32,
+    /// Played once before despawning. The sprite is flipped upside down if unset
+    pub animation: Option<String>,
+    /// Seconds until the defeated enemy is despawned
+    pub despawn_delay: f32,
+}
+
+impl Default for DefeatProperties {
+    fn default() -> Self {
+        Self {
+            stomp: false,
+            dash: false,
+            projectile: false,
+            score: 0,
+            health: 1,
+            bounce_velocity: 250.0,
+            animation: None,
+            despawn_delay: 0.5,
+        }
+    }
+}
+
+#[derive(Component, Clone)]
+pub struct Defeatable(pub DefeatProperties);
+
+/// Remaining hits of a `Defeatable`
+#[derive(Component, Clone, Copy)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+impl Health {
+    pub fn new(max: u32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn fraction(&self) -> f32 {
+        self.current as f32 / self.max.max(1) as f32
+    }
+}
+
+/// Despawned once the timer finishes
+#[derive(Component)]
+pub struct Defeated(Timer);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DefeatCause {
+    Stomp,
+    Dash,
+    Projectile,
+}
+
+#[derive(Event)]
+pub struct EnemyDefeatEvent {
+    pub player: Entity,
+    /// Entity with the `Defeatable` component. Either an enemy or a part of a prefab
+    pub enemy: Entity,
+    pub cause: DefeatCause,
+}
+
+/// The player collider landed on top of the other collider
+pub fn is_stomp(rapier_context: &RapierContext, player_collider: Entity, other: Entity) -> bool {
+    let Some(pair) = rapier_context.contact_pair(player_collider, other) else {
+        return false;
+    };
+    // The normal points from the first to the second collider
+    let sign = if pair.collider1() == player_collider {
+        1.0
+    } else {
+        -1.0
+    };
+    pair.manifolds()
+        .any(|manifold| manifold.normal().y * sign < -STOMP_NORMAL)
+}
+
+#[allow(clippy::type_complexity)]
+fn handle_defeat(
+    mut commands: Commands,
+    mut er_defeat: EventReader<EnemyDefeatEvent>,
+    mut progress: ResMut<RunProgress>,
+    mut q_enemies: Query<
+        (
+            &Defeatable,
+            &mut Health,
+            Option<&Children>,
+            Option<&mut AnimatedSprite>,
+            Option<&mut Sprite>,
+            Option<&mut RigidBody>,
+        ),
+        Without<Defeated>,
+    >,
+    mut q_velocity: Query<&mut Velocity>,
+    q_hitboxes: Query<(), With<Hitbox>>,
+) {
+    let mut hit = vec![];
+    for event in er_defeat.read() {
+        // Multiple colliders can touch the same enemy
+        if hit.contains(&event.enemy) {
+            continue;
+        }
+        let Ok((defeatable, mut health, children, animation, sprite, body)) =
+            q_enemies.get_mut(event.enemy)
+        else {
+            continue;
+        };
+        hit.push(event.enemy);
+        let props = &defeatable.0;
+        if event.cause == DefeatCause::Stomp {
+            if let Ok(mut velocity) = q_velocity.get_mut(event.player) {
+                velocity.linvel.y = props.bounce_velocity;
+            }
+        }
+        health.current = health.current.saturating_sub(1);
+        if health.current > 0 {
+            continue;
+        }
+        info!("Defeated {:?} by {:?}", event.enemy, event.cause);
+        progress.score += props.score;
+        match (&props.animation, animation, sprite) {
+            (Some(name), Some(mut animation), _) => {
+                animation.queue_animation(name, false, None);
+            }
+            (_, _, Some(mut sprite)) => sprite.flip_y = true,
+            _ => (),
+        }
+        // Keep the body in place without colliders
+        if let Some(mut body) = body {
+            *body = RigidBody::KinematicPositionBased;
+        }
+        for child in children.into_iter().flatten() {
+            if q_hitboxes.contains(*child) {
+                commands.entity(*child).despawn_recursive();
+            }
+        }
+        commands
+            .entity(event.enemy)
+            .remove::<(
+                FrameHitboxes,
+                AnimationStateMachine,
+                HorizontalSpeed,
+                Hopper,
+                SineFlyer,
+                Charger,
+                Patroller,
+                Weapon,
+            )>()
+            .insert(Defeated(Timer::from_seconds(
+                props.despawn_delay,
+                TimerMode::Once,
+            )));
+    }
+}
+
+fn despawn_defeated(
+    mut commands: Commands,
+    time: Res<Time<Virtual>>,
+    mut q_defeated: Query<(Entity, &mut Defeated)>,
+) {
+    for (entity, mut defeated) in &mut q_defeated {
+        if defeated.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+pub struct DefeatPlugin;
+
+impl Plugin for DefeatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (handle_defeat, despawn_defeated).run_if(in_state(GameState::Running)),
+        )
+        .add_event::<EnemyDefeatEvent>();
+    }
+}
@@ -1,21 +1,27 @@
-use std::time::Duration;
-
 use bevy::prelude::*;
 use bevy_rapier2d::dynamics::LockedAxes;
-use rand::{seq::IteratorRandom, Rng};
-
-use crate::{model::Models, physics::ControllerBundle, AppState, GameState};
 
-use std::collections::HashMap;
+use crate::{
+    aseprite::AsepriteSheet,
+    model::{Model, Models},
+    physics::{CollisionGroup, ControllerBundle},
+    spawner::SpawnTable,
+    AppState,
+};
 
 use super::character::CharacterProperties;
 
 #[derive(Component, Default)]
 pub struct Enemy;
 
+/// Name of the enemy definition the enemy was spawned from
+#[derive(Component, Default, Clone)]
+pub struct EnemyKind(pub String);
+
 #[derive(Bundle, Default)]
 pub struct EnemyBundle {
     pub enemy: Enemy,
+    pub kind: EnemyKind,
     pub controller: ControllerBundle,
 }
 
@@ -29,47 +35,56 @@ fn load_assets(mut commands: Commands, asset_server: Res<AssetServer>) {
 }
 
 fn setup_enemies(
-    mut commands: Commands,
+    mut models: ResMut<Models>,
+    mut spawn_table: ResMut<SpawnTable>,
     mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
     asset_server: Res<AssetServer>,
     mut enemy_assets: ResMut<Assets<CharacterProperties>>,
+    aseprite_sheets: Res<Assets<AsepriteSheet>>,
     enemy_handle: Res<EnemyHandle>,
 ) {
-    let mut model_map = HashMap::new();
-    if let Some(enemies) = enemy_assets.remove(enemy_handle.0.id()) {
+    if let Some(enemies) = CharacterProperties::take_loaded(
+        &mut enemy_assets,
+        &enemy_handle.0,
+        &asset_server,
+        &aseprite_sheets,
+    ) {
         info!("Setting up enemies!");
         for enemy in &enemies.characters {
-            model_map.insert(
-                enemy.name.clone(),
-                enemy.get_model(&asset_server, &mut texture_atlas_layouts),
-            );
+            let Some(model) =
+                enemy.get_model(&asset_server, &mut texture_atlas_layouts, &aseprite_sheets)
+            else {
+                continue;
+            };
+            models.models.insert(enemy.name.clone(), model);
+            spawn_table
+                .entries
+                .insert(enemy.name.clone(), enemy.spawn.clone());
         }
-        commands.insert_resource(Models { models: model_map });
     }
 }
 
-fn spawn_enemy(
-    mut commands: Commands,
-    time: Res<Time<Virtual>>,
-    mut last_update: Local<Duration>,
-    models: Res<Models>,
-) {
-    let next_spawn_ms = rand::thread_rng().gen_range(800..1500);
-    // Generate new enemys
-    if time.elapsed() - *last_update > Duration::from_millis(next_spawn_ms) {
-        *last_update = time.elapsed();
-        // spawn new
-        if let Some(model_key) = models.models.keys().choose(&mut rand::thread_rng()) {
-            let mut model = models.models.get(model_key).unwrap().clone();
-            model.spritesheet.transform.translation.x = 500.0;
-            let enemy = EnemyBundle {
-                ..Default::default()
-            };
-            let mut entity_commands = commands.spawn(enemy);
-            entity_commands.insert(LockedAxes::ROTATION_LOCKED);
-            model.spawn(entity_commands);
-        }
-    }
+/// Spawns an enemy at the given x position. `height` is added to the default height of the model
+pub fn spawn_enemy(
+    commands: &mut Commands,
+    name: &str,
+    model: &Model,
+    x: f32,
+    height: f32,
+) -> Entity {
+    let mut model = model.clone();
+    model.spritesheet.transform.translation.x = x;
+    model.spritesheet.transform.translation.y += height;
+    model.set_collision_layer(CollisionGroup::Enemy);
+    let enemy = EnemyBundle {
+        kind: EnemyKind(name.to_string()),
+        ..Default::default()
+    };
+    let mut entity_commands = commands.spawn(enemy);
+    entity_commands.insert(LockedAxes::ROTATION_LOCKED);
+    let entity = entity_commands.id();
+    model.spawn(entity_commands);
+    entity
 }
 
 fn cleanup_enemy(mut commands: Commands, q_enemy: Query<Entity, With<Enemy>>) {
@@ -84,7 +99,6 @@ impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, load_assets)
             .add_systems(Update, setup_enemies)
-            .add_systems(Update, spawn_enemy.run_if(in_state(GameState::Running)))
             .add_systems(OnEnter(AppState::Game), cleanup_enemy)
             .init_resource::<EnemyHandle>();
     }
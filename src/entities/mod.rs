@@ -1,3 +1,8 @@
+pub mod behavior;
 pub mod character;
+pub mod defeat;
 pub mod enemy;
 pub mod player;
+pub mod prefab;
+pub mod projectile;
+pub mod state_machine;
@@ -1,26 +1,264 @@
 use bevy::{input::touch::TouchPhase, prelude::*};
-use bevy_2d_animations::{Animated, AnimatedSprite};
 use bevy_rapier2d::prelude::*;
 use leafwing_input_manager::{action_state::ActionState, input_map::InputMap, InputManagerBundle};
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    aseprite::AsepriteSheet,
+    collision::{Contact, ContactAppExt},
+    config::GameConfig,
+    ground::platform::{DropThrough, Platform},
     input::PlayerAction,
     physics::{CollisionGroup, ControllerBundle},
-    AppState, GameState,
+    spawner::planner::PlayerReach,
+    AppState, GameState, KillPlane, RunProgress,
 };
 
 use super::{
-    character::{CharacterBundle, CharacterProperty},
+    character::{CharacterBundle, CharacterProperties, CharacterProperty, ColliderType},
+    defeat::{is_stomp, DefeatCause, Defeatable, EnemyDefeatEvent},
     enemy::Enemy,
+    prefab::{Pickup, PrefabPart},
+    projectile::{Projectile, ProjectileOwner},
 };
 
+/// Additional speed per unit the player is behind its position in the run
+const CATCH_UP: f32 = 2.0;
+
 #[derive(Component, Default)]
 pub struct Player {
     pub is_grounded: bool,
+    /// Standing on a platform that can be dropped through
+    pub on_platform: bool,
+    /// Position the player returns to at the start of a run
+    pub spawn: Vec3,
+}
+
+/// Looks for ground below the body. Offsets and lengths are scaled with the body
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(tag = "type")]
+pub enum GroundProbe {
+    #[serde(rename = "ray")]
+    Ray {
+        #[serde(default)]
+        origin: Vec2,
+        length: f32,
+    },
+    /// Moves the shape down. Usually matches the feet of the character
+    #[serde(rename = "shape")]
+    Shape {
+        #[serde(default)]
+        origin: Vec2,
+        collider: ColliderType,
+        distance: f32,
+    },
+}
+
+impl GroundProbe {
+    /// Ground hit by the probe
+    fn cast(
+        &self,
+        rapier_context: &RapierContext,
+        position: Vec2,
+        scale: Vec2,
+        filter: QueryFilter,
+    ) -> Option<Entity> {
+        match self {
+            Self::Ray { origin, length } => rapier_context
+                .cast_ray(
+                    position + *origin * scale,
+                    Vec2::NEG_Y,
+                    length * scale.y,
+                    false,
+                    filter,
+                )
+                .map(|(entity, _toi)| entity),
+            Self::Shape {
+                origin,
+                collider,
+                distance,
+            } => {
+                let mut shape = Collider::from(collider.clone());
+                shape.set_scale(scale, 1);
+                rapier_context
+                    .cast_shape(
+                        position + *origin * scale,
+                        0.0,
+                        Vec2::NEG_Y,
+                        &shape,
+                        distance * scale.y,
+                        true,
+                        filter,
+                    )
+                    .map(|(entity, _toi)| entity)
+            }
+        }
+    }
+}
+
+/// The player is grounded if any of the probes hits the ground
+#[derive(Component, Deserialize, Serialize, Clone)]
+pub struct GroundCheck {
+    pub probes: Vec<GroundProbe>,
+}
+
+impl GroundCheck {
+    /// Ground hit by any of the probes
+    pub fn find_ground(
+        &self,
+        rapier_context: &RapierContext,
+        transform: &Transform,
+        filter: QueryFilter,
+    ) -> Option<Entity> {
+        self.probes.iter().find_map(|probe| {
+            probe.cast(
+                rapier_context,
+                transform.translation.xy(),
+                transform.scale.xy(),
+                filter,
+            )
+        })
+    }
+}
+
+impl Default for GroundCheck {
+    fn default() -> Self {
+        Self {
+            probes: vec![GroundProbe::Ray {
+                origin: Vec2::ZERO,
+                length: 17.0,
+            }],
+        }
+    }
+}
+
+#[derive(Component, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct JumpProperties {
+    /// Upwards velocity at the start of a jump. Defaults to the velocity of the game config
+    pub velocity: Option<f32>,
+    /// Multiplies the upwards velocity when the jump is released early
+    pub release_factor: f32,
+    /// Seconds after leaving the ground in which the player can still jump
+    pub coyote_time: f32,
+    /// Seconds a jump pressed before landing is remembered
+    pub buffer_time: f32,
+}
+
+impl Default for JumpProperties {
+    fn default() -> Self {
+        Self {
+            velocity: None,
+            release_factor: 0.5,
+            coyote_time: 0.1,
+            buffer_time: 0.1,
+        }
+    }
 }
 
 #[derive(Component, Default)]
-pub struct PlayerCamera;
+pub struct JumpState {
+    /// Remaining coyote time
+    pub coyote: f32,
+    /// Remaining time of a buffered jump
+    pub buffer: f32,
+    /// Rising from a jump that can still be cut short
+    pub rising: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct FastFallProperties {
+    /// Downwards speed while fast falling
+    pub velocity: f32,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct GlideProperties {
+    /// Maximum downwards speed while gliding
+    pub fall_speed: f32,
+    /// Seconds of gliding before landing again
+    pub duration: f32,
+}
+
+/// Abilities used while airborne. Limits reset on landing
+#[derive(Component, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct AirProperties {
+    /// Jumps before landing again
+    pub extra_jumps: u32,
+    /// Defaults to the velocity of the ground jump
+    pub extra_jump_velocity: Option<f32>,
+    /// Triggered by `Duck`
+    pub fast_fall: Option<FastFallProperties>,
+    /// Holding `Jump` while falling
+    pub glide: Option<GlideProperties>,
+}
+
+#[derive(Component, Default)]
+pub struct AirState {
+    pub jumps_left: u32,
+    pub glide_left: f32,
+    /// Rising from an extra jump
+    pub air_jumping: bool,
+    pub fast_falling: bool,
+    /// Jump was pressed again while falling without extra jumps left and is still held
+    pub glide_held: bool,
+    pub gliding: bool,
+}
+
+impl AirState {
+    fn land(&mut self, props: &AirProperties) {
+        *self = Self {
+            jumps_left: props.extra_jumps,
+            glide_left: props.glide.as_ref().map_or(0.0, |glide| glide.duration),
+            ..Default::default()
+        };
+    }
+}
+
+/// Present while the player falls into a pit. The run stops and the camera follows the fall
+#[derive(Component, Default)]
+pub struct PitFall;
+
+/// Present while the player is dashing. Defeats enemies that can be dashed through and
+/// protects from hits
+#[derive(Component, Default)]
+pub struct Dashing;
+
+#[derive(Component, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct DashProperties {
+    /// Speed on top of the scrolling
+    pub speed: f32,
+    /// Seconds
+    pub duration: f32,
+    /// Seconds from the start of a dash until the next one
+    pub cooldown: f32,
+}
+
+impl Default for DashProperties {
+    fn default() -> Self {
+        Self {
+            speed: 200.0,
+            duration: 0.25,
+            cooldown: 1.5,
+        }
+    }
+}
+
+#[derive(Component, Default)]
+pub struct DashState {
+    /// Seconds left of the current dash
+    pub remaining: f32,
+    /// Seconds until the next dash
+    pub cooldown: f32,
+}
+
+#[derive(Component, Default)]
+pub struct PlayerCamera {
+    /// Position relative to the distance of the run
+    pub offset: Vec2,
+}
 
 #[derive(Bundle, Default)]
 pub struct PlayerCameraBundle {
@@ -33,11 +271,37 @@ pub struct PlayerBundle {
     pub character: CharacterBundle,
 
     pub player: Player,
+    pub jump: JumpState,
+    pub air: AirState,
+    pub dash: DashState,
 
     pub input: InputManagerBundle<PlayerAction>,
     pub locked_axes: LockedAxes,
 }
 
+pub type PlayerEnemyContact = Contact<Player, Enemy>;
+pub type PlayerProjectileContact = Contact<Player, Projectile>;
+pub type KillPlaneContact = Contact<Player, KillPlane>;
+
+/// Reason the run ended
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DeathCause {
+    Hit,
+    Fall,
+    /// Blocked by a step or an obstacle until the camera left the player behind
+    LeftBehind,
+}
+
+impl DeathCause {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::Hit => "Hit by an enemy",
+            Self::Fall => "Fell into a pit",
+            Self::LeftBehind => "Fell behind",
+        }
+    }
+}
+
 #[derive(Event)]
 struct PlayerHitEvent {
     pub player: Entity,
@@ -47,6 +311,7 @@ struct PlayerHitEvent {
 #[derive(Event)]
 struct PlayerDeathEvent {
     pub player: Entity,
+    pub cause: DeathCause,
 }
 
 impl Default for PlayerBundle {
@@ -54,19 +319,22 @@ impl Default for PlayerBundle {
         let mut input_map = InputMap::default();
         input_map.insert(PlayerAction::Jump, KeyCode::Space);
         input_map.insert(PlayerAction::Duck, KeyCode::ArrowDown);
+        input_map.insert(PlayerAction::Shoot, KeyCode::KeyX);
+        input_map.insert(PlayerAction::Dash, KeyCode::ShiftLeft);
         input_map.insert(PlayerAction::DebugRenderer, KeyCode::F1);
         input_map.insert(PlayerAction::Pause, KeyCode::Escape);
 
         Self {
             character: CharacterBundle {
-                controller: ControllerBundle {
-                    ..Default::default()
-                },
+                controller: ControllerBundle::new(CollisionGroup::Player),
                 ..Default::default()
             },
             player: Player::default(),
+            jump: JumpState::default(),
+            air: AirState::default(),
+            dash: DashState::default(),
             input: InputManagerBundle::with_map(input_map),
-            locked_axes: LockedAxes::ROTATION_LOCKED | LockedAxes::TRANSLATION_LOCKED_X,
+            locked_axes: LockedAxes::ROTATION_LOCKED,
         }
     }
 }
@@ -123,57 +391,268 @@ fn toggle_debug(
 }
 
 fn player_jump(
+    time: Res<Time<Virtual>>,
+    config: Res<GameConfig>,
+    mut q_player: Query<(
+        &mut Velocity,
+        &mut JumpState,
+        &JumpProperties,
+        &ActionState<PlayerAction>,
+        &Player,
+        Option<(&mut AirState, &AirProperties)>,
+    )>,
+) {
+    for (mut velocity, mut jump, props, input_data, player, mut air) in &mut q_player {
+        let jump_velocity = props.velocity.unwrap_or(config.jump_velocity);
+        // Still touching the ground right after a jump
+        if player.is_grounded && velocity.linvel.y <= 0.0 {
+            jump.coyote = props.coyote_time;
+            jump.rising = false;
+            if let Some((air_state, air_props)) = &mut air {
+                air_state.land(air_props);
+            }
+        } else {
+            jump.coyote -= time.delta_seconds();
+        }
+        if input_data.just_pressed(&PlayerAction::Jump) {
+            jump.buffer = props.buffer_time;
+        } else {
+            jump.buffer -= time.delta_seconds();
+        }
+        if jump.buffer > 0.0 && jump.coyote > 0.0 {
+            info!("jump");
+            velocity.linvel.y = jump_velocity;
+            jump.buffer = 0.0;
+            jump.coyote = 0.0;
+            jump.rising = true;
+        } else if let Some((air_state, air_props)) = air.as_mut().filter(|(air_state, _)| {
+            input_data.just_pressed(&PlayerAction::Jump) && air_state.jumps_left > 0
+        }) {
+            info!("air jump");
+            velocity.linvel.y = air_props.extra_jump_velocity.unwrap_or(jump_velocity);
+            air_state.jumps_left -= 1;
+            air_state.air_jumping = true;
+            air_state.fast_falling = false;
+            jump.buffer = 0.0;
+            jump.rising = true;
+        } else if jump.rising {
+            if velocity.linvel.y <= 0.0 {
+                jump.rising = false;
+            } else if !input_data.pressed(&PlayerAction::Jump) {
+                velocity.linvel.y *= props.release_factor;
+                jump.rising = false;
+            }
+        }
+    }
+}
+
+/// Runs along with the camera. Catches up after being held back by an obstacle
+fn advance_player(
+    progress: Res<RunProgress>,
+    config: Res<GameConfig>,
     mut q_player: Query<
         (
             &mut Velocity,
-            &ActionState<PlayerAction>,
-            &Player,
-            &mut AnimatedSprite,
+            &Transform,
+            Option<&DashProperties>,
+            Has<Dashing>,
         ),
-        With<Player>,
+        (With<Player>, Without<PitFall>),
     >,
 ) {
-    for (mut velocity, input_data, player, mut animation) in &mut q_player {
-        if input_data.just_pressed(&PlayerAction::Jump) && player.is_grounded {
-            info!("jump");
-            velocity.linvel = Vec2::new(0.0, 1.0) * 300.0;
-            animation.queue_animation("jump", false, None);
+    for (mut velocity, transform, dash, dashing) in &mut q_player {
+        let behind = progress.distance - transform.translation.x;
+        velocity.linvel.x = config.scroll_speed + behind * CATCH_UP;
+        if let Some(dash) = dash.filter(|_| dashing) {
+            velocity.linvel.x += dash.speed;
         }
     }
 }
 
+/// Dashes forward. The player falls back to its position in the run afterwards
+fn player_dash(
+    mut commands: Commands,
+    time: Res<Time<Virtual>>,
+    mut q_player: Query<(
+        Entity,
+        &mut DashState,
+        &DashProperties,
+        &mut Velocity,
+        &ActionState<PlayerAction>,
+        Has<Dashing>,
+    )>,
+) {
+    for (entity, mut dash, props, mut velocity, input_data, dashing) in &mut q_player {
+        dash.cooldown = (dash.cooldown - time.delta_seconds()).max(0.0);
+        if dashing {
+            dash.remaining -= time.delta_seconds();
+            // Dashes in a straight line
+            velocity.linvel.y = 0.0;
+            if dash.remaining <= 0.0 {
+                commands.entity(entity).remove::<Dashing>();
+            }
+        } else if input_data.just_pressed(&PlayerAction::Dash) && dash.cooldown <= 0.0 {
+            info!("dash");
+            dash.remaining = props.duration;
+            dash.cooldown = props.cooldown;
+            commands.entity(entity).insert(Dashing);
+        }
+    }
+}
+
+fn reset_player(
+    mut commands: Commands,
+    mut q_player: Query<(
+        Entity,
+        &Player,
+        &mut Transform,
+        &mut Velocity,
+        &mut JumpState,
+        &mut AirState,
+    )>,
+) {
+    for (entity, player, mut transform, mut velocity, mut jump, mut air) in &mut q_player {
+        transform.translation = player.spawn;
+        *velocity = Velocity::zero();
+        *jump = JumpState::default();
+        *air = AirState::default();
+        commands
+            .entity(entity)
+            .remove::<(PitFall, Dashing)>()
+            .insert(DashState::default());
+    }
+}
+
 fn ground_check(
-    mut q_player: Query<(&mut Player, &Transform, &mut AnimatedSprite)>,
+    mut q_player: Query<(
+        &mut Player,
+        &GroundCheck,
+        &Transform,
+        &Velocity,
+        Has<DropThrough>,
+    )>,
+    q_platforms: Query<(), With<Platform>>,
     rapier_context: Res<RapierContext>,
 ) {
-    for (mut player, transform, mut animation) in &mut q_player {
-        let source = transform.translation.xy();
-        let direction = Vec2::new(0.0, -1.0);
-        // TODO: read from player
-        let toi = 17.0;
-        if let Some((_hit_entity, _intersection_toi)) = rapier_context.cast_ray(
-            source,
-            direction,
-            toi,
-            false,
-            QueryFilter::new().groups(CollisionGroups::new(
-                CollisionGroup::Common.group(),
+    for (mut player, ground_check, transform, velocity, dropping) in &mut q_player {
+        // Platforms only carry the player while falling onto them
+        let platforms_solid = velocity.linvel.y <= 0.0 && !dropping;
+        let predicate = |entity: Entity| platforms_solid || !q_platforms.contains(entity);
+        let filter = QueryFilter::new()
+            .groups(CollisionGroups::new(
+                CollisionGroup::Player.group(),
                 CollisionGroup::Wall.group(),
-            )),
-        ) {
-            if !player.is_grounded {
-                player.is_grounded = true;
-                animation.queue_animation("idle", true, None);
+            ))
+            .predicate(&predicate);
+        let hit = ground_check.find_ground(&rapier_context, transform, filter);
+        player.is_grounded = hit.is_some();
+        player.on_platform = hit.is_some_and(|entity| q_platforms.contains(entity));
+    }
+}
+
+fn air_abilities(
+    time: Res<Time<Virtual>>,
+    mut q_player: Query<(
+        &mut Velocity,
+        &mut AirState,
+        &AirProperties,
+        &ActionState<PlayerAction>,
+        &Player,
+    )>,
+) {
+    for (mut velocity, mut air, props, input_data, player) in &mut q_player {
+        if player.is_grounded {
+            continue;
+        }
+        if velocity.linvel.y <= 0.0 {
+            air.air_jumping = false;
+        }
+        if let Some(fast_fall) = &props.fast_fall {
+            if input_data.just_pressed(&PlayerAction::Duck) {
+                air.fast_falling = true;
+            }
+            if air.fast_falling {
+                velocity.linvel.y = velocity.linvel.y.min(-fast_fall.velocity);
+            }
+        }
+        air.gliding = false;
+        if let Some(glide) = &props.glide {
+            if input_data.just_pressed(&PlayerAction::Jump)
+                && air.jumps_left == 0
+                && velocity.linvel.y <= 0.0
+            {
+                air.glide_held = true;
+            } else if !input_data.pressed(&PlayerAction::Jump) {
+                air.glide_held = false;
+            }
+            if air.glide_held
+                && !air.fast_falling
+                && air.glide_left > 0.0
+                && velocity.linvel.y <= 0.0
+            {
+                air.gliding = true;
+                air.glide_left -= time.delta_seconds();
+                velocity.linvel.y = velocity.linvel.y.max(-glide.fall_speed);
             }
-        } else {
-            player.is_grounded = false;
         }
     }
 }
 
-fn player_duck() {}
+/// Only a pit can take the player below the surface of the ground
+fn fall_check(
+    mut commands: Commands,
+    q_player: Query<(Entity, &Player, &Transform), Without<PitFall>>,
+) {
+    for (entity, player, transform) in &q_player {
+        if !player.is_grounded && transform.translation.y < 0.0 {
+            commands.entity(entity).insert(PitFall);
+        }
+    }
+}
+
+/// Ends the run once the player is pushed out past the left edge of the camera
+fn left_behind_check(
+    q_camera: Query<(&Transform, &OrthographicProjection), With<PlayerCamera>>,
+    q_player: Query<(Entity, &Transform), (With<Player>, Without<PitFall>)>,
+    mut ew_death: EventWriter<PlayerDeathEvent>,
+) {
+    let Ok((camera, projection)) = q_camera.get_single() else {
+        return;
+    };
+    let left_edge = camera.translation.x + projection.area.min.x;
+    for (entity, transform) in &q_player {
+        if transform.translation.x < left_edge {
+            ew_death.send(PlayerDeathEvent {
+                player: entity,
+                cause: DeathCause::LeftBehind,
+            });
+        }
+    }
+}
 
-fn player_death() {}
+/// Drops through the platform the player is standing on
+fn player_duck(
+    mut commands: Commands,
+    q_player: Query<(Entity, &ActionState<PlayerAction>, &Player), Without<DropThrough>>,
+) {
+    for (entity, input_data, player) in &q_player {
+        if input_data.just_pressed(&PlayerAction::Duck) && player.on_platform {
+            commands.entity(entity).insert(DropThrough::default());
+        }
+    }
+}
+
+fn kill_plane_contact(
+    mut er_contact: EventReader<KillPlaneContact>,
+    mut ew_death: EventWriter<PlayerDeathEvent>,
+) {
+    for contact in er_contact.read().filter(|contact| contact.started()) {
+        ew_death.send(PlayerDeathEvent {
+            player: contact.a,
+            cause: DeathCause::Fall,
+        });
+    }
+}
 
 #[derive(Resource, Default)]
 pub struct PlayerHandle(Handle<CharacterProperty>);
@@ -189,12 +668,29 @@ fn setup_player(
     mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
     asset_server: Res<AssetServer>,
     mut player_assets: ResMut<Assets<CharacterProperty>>,
+    aseprite_sheets: Res<Assets<AsepriteSheet>>,
     player_handle: Res<PlayerHandle>,
 ) {
-    if let Some(player) = player_assets.remove(player_handle.0.id()) {
-        let model = player.get_model(&asset_server, &mut texture_atlas_layouts);
+    if let Some(player) = CharacterProperties::take_loaded(
+        &mut player_assets,
+        &player_handle.0,
+        &asset_server,
+        &aseprite_sheets,
+    ) {
+        let Some(mut model) =
+            player.get_model(&asset_server, &mut texture_atlas_layouts, &aseprite_sheets)
+        else {
+            return;
+        };
+        model.set_collision_layer(CollisionGroup::Player);
+        model.ground_check.get_or_insert_with(GroundCheck::default);
+        model.jump.get_or_insert_with(JumpProperties::default);
+        model.air.get_or_insert_with(AirProperties::default);
+        model.dash.get_or_insert_with(DashProperties::default);
+        commands.insert_resource(PlayerReach::from_model(&model));
         // spawn player
-        let player_bundle = PlayerBundle::default();
+        let mut player_bundle = PlayerBundle::default();
+        player_bundle.player.spawn = model.spritesheet.transform.translation;
 
         let entity_commands = commands.spawn(player_bundle);
         model.spawn(entity_commands);
@@ -204,46 +700,100 @@ fn setup_player(
 fn handle_hit(
     mut er_hit: EventReader<PlayerHitEvent>,
     mut ew_death: EventWriter<PlayerDeathEvent>,
+    q_dashing: Query<(), With<Dashing>>,
 ) {
     for event in er_hit.read() {
+        if q_dashing.contains(event.player) {
+            continue;
+        }
         ew_death.send(PlayerDeathEvent {
             player: event.player,
+            cause: DeathCause::Hit,
         });
     }
 }
 
-fn handle_death(mut er_death: EventReader<PlayerDeathEvent>, mut commands: Commands) {
-    for _event in er_death.read() {
+fn handle_death(
+    mut er_death: EventReader<PlayerDeathEvent>,
+    mut commands: Commands,
+    mut progress: ResMut<RunProgress>,
+) {
+    for event in er_death.read() {
+        info!("player {:?} died: {:?}", event.player, event.cause);
+        progress.death.get_or_insert(event.cause);
         commands.insert_resource(NextState(Some(AppState::GameOver)));
     }
 }
 
-fn collision(
-    mut er_collision: EventReader<CollisionEvent>,
-    q_enemy: Query<Entity, With<Enemy>>,
-    q_player: Query<Entity, With<Player>>,
+#[allow(clippy::too_many_arguments)]
+fn enemy_contact(
+    mut er_contact: EventReader<PlayerEnemyContact>,
+    rapier_context: Res<RapierContext>,
+    q_dashing: Query<(), With<Dashing>>,
+    q_defeatable: Query<&Defeatable>,
+    q_parts: Query<&PrefabPart>,
+    q_pickups: Query<(), With<Pickup>>,
     q_parents: Query<&Parent>,
     mut ew_hit: EventWriter<PlayerHitEvent>,
+    mut ew_defeat: EventWriter<EnemyDefeatEvent>,
 ) {
-    for event in er_collision.read() {
-        if let CollisionEvent::Started(ent1, ent2, _flags) = event {
-            if let (Ok(ent1_parent), Ok(ent2_parent)) = (q_parents.get(*ent1), q_parents.get(*ent2))
-            {
-                let (player, other) = if q_player.contains(ent1_parent.get()) {
-                    (ent1_parent.get(), ent2_parent.get())
-                } else if q_player.contains(*ent2) {
-                    (ent2_parent.get(), ent1_parent.get())
-                } else {
-                    continue;
-                };
-                // we got a player collision with some other
-
-                // Hit with enemy -> send event
-                if let Ok(enemy) = q_enemy.get(other) {
-                    ew_hit.send(PlayerHitEvent { player, enemy });
-                    info!("player with enemy collision! {:?} {:?}", player, enemy);
+    for contact in er_contact.read().filter(|contact| contact.started()) {
+        let player = contact.a;
+        let other = contact.b_collider;
+        // Colliders can be nested inside of prefab parts. The closest part decides
+        for ancestor in std::iter::once(other).chain(q_parents.iter_ancestors(other)) {
+            // Handled by the pickup contact
+            if q_pickups.contains(ancestor) {
+                break;
+            }
+            if let Ok(defeatable) = q_defeatable.get(ancestor) {
+                let cause =
+                    if defeatable.0.stomp && is_stomp(&rapier_context, contact.a_collider, other) {
+                        Some(DefeatCause::Stomp)
+                    } else if defeatable.0.dash && q_dashing.contains(player) {
+                        Some(DefeatCause::Dash)
+                    } else {
+                        None
+                    };
+                if let Some(cause) = cause {
+                    ew_defeat.send(EnemyDefeatEvent {
+                        player,
+                        enemy: ancestor,
+                        cause,
+                    });
+                    break;
                 }
             }
+            if q_parts.get(ancestor).is_ok_and(|part| !part.harmful) {
+                break;
+            }
+            // Hit with enemy -> send event
+            if ancestor == contact.b {
+                ew_hit.send(PlayerHitEvent {
+                    player,
+                    enemy: contact.b,
+                });
+                info!("player with enemy collision! {:?} {:?}", player, contact.b);
+                break;
+            }
+        }
+    }
+}
+
+fn projectile_contact(
+    mut er_contact: EventReader<PlayerProjectileContact>,
+    q_projectiles: Query<&Projectile>,
+    mut ew_hit: EventWriter<PlayerHitEvent>,
+) {
+    for contact in er_contact.read().filter(|contact| contact.started()) {
+        if q_projectiles
+            .get(contact.b)
+            .is_ok_and(|projectile| projectile.owner == ProjectileOwner::Enemy)
+        {
+            ew_hit.send(PlayerHitEvent {
+                player: contact.a,
+                enemy: contact.b,
+            });
         }
     }
 }
@@ -255,14 +805,20 @@ impl Plugin for PlayerPlugin {
         app.add_systems(Startup, load_assets)
             .add_systems(Update, setup_player)
             .add_systems(Update, pause_game.run_if(in_state(AppState::Game)))
+            .add_systems(OnEnter(AppState::Game), reset_player)
             .add_systems(
                 Update,
                 (
-                    player_jump,
+                    advance_player,
+                    player_dash,
+                    (player_jump, air_abilities).chain(),
                     player_duck,
-                    player_death,
                     ground_check,
-                    collision,
+                    fall_check,
+                    left_behind_check,
+                    kill_plane_contact,
+                    enemy_contact,
+                    projectile_contact,
                     handle_hit,
                     handle_death,
                     inject_touch,
@@ -271,6 +827,9 @@ impl Plugin for PlayerPlugin {
                     .run_if(in_state(AppState::Game)),
             )
             .init_resource::<PlayerHandle>()
+            .add_contact_pair::<Player, Enemy>()
+            .add_contact_pair::<Player, Projectile>()
+            .add_contact_pair::<Player, KillPlane>()
             .add_event::<PlayerHitEvent>()
             .add_event::<PlayerDeathEvent>();
         #[cfg(debug_assertions)]
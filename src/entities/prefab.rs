@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_rapier2d::{
+    dynamics::RigidBody,
+    geometry::{ActiveEvents, Collider, Group, SolverGroups},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aseprite::AsepriteSheet,
+    collision::{Contact, ContactAppExt},
+    model::Models,
+    physics::CollisionGroup,
+    spawner::{SpawnProperties, SpawnTable},
+    GameState, RunProgress,
+};
+
+use super::{
+    character::{CharacterProperties, ColliderType},
+    enemy::{EnemyBundle, EnemyKind},
+    player::Player,
+};
+
+/// Part of a prefab. Parts that are not harmful don't hurt the player on contact
+#[derive(Component, Clone, Copy)]
+pub struct PrefabPart {
+    pub harmful: bool,
+}
+
+/// Collected by the player on contact
+#[derive(Component, Clone, Copy, Default)]
+pub struct Pickup {
+    pub score: u32,
+}
+
+pub type PickupCollected = Contact<Player, Pickup>;
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(tag = "type")]
+pub enum PrefabPartProperties {
+    /// Enemy or prop from the model list
+    #[serde(rename = "model")]
+    Model {
+        model: String,
+        #[serde(default)]
+        offset: Vec2,
+        /// Harmless models can be used as platforms
+        #[serde(default = "default_harmful")]
+        harmful: bool,
+    },
+    /// Invisible solid collider
+    #[serde(rename = "collider")]
+    Collider {
+        collider: ColliderType,
+        #[serde(default)]
+        offset: Vec2,
+    },
+    #[serde(rename = "pickup")]
+    Pickup {
+        model: String,
+        #[serde(default)]
+        offset: Vec2,
+        #[serde(default)]
+        score: u32,
+    },
+}
+
+fn default_harmful() -> bool {
+    true
+}
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct PrefabProperties {
+    pub name: String,
+    pub parts: Vec<PrefabPartProperties>,
+    pub spawn: SpawnProperties,
+}
+
+#[derive(Deserialize, Serialize, Asset, TypePath)]
+pub struct PrefabList {
+    pub prefabs: Vec<PrefabProperties>,
+}
+
+#[derive(Resource, Default)]
+pub struct Prefabs {
+    pub prefabs: HashMap<String, PrefabProperties>,
+}
+
+impl PrefabProperties {
+    /// Area covered by all solid and harmful parts relative to the root
+    pub fn bounds(&self, models: &Models) -> Option<Rect> {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                PrefabPartProperties::Model { model, offset, .. } => {
+                    let model = models.models.get(model)?;
+                    let bounds = model.collider_bounds()?;
+                    let center = offset + model.spritesheet.transform.translation.xy();
+                    Some(Rect::from_center_size(
+                        bounds.center() + center,
+                        bounds.size(),
+                    ))
+                }
+                PrefabPartProperties::Collider { collider, offset } => {
+                    let aabb = Collider::from(collider.clone()).raw.compute_local_aabb();
+                    Some(Rect::new(
+                        aabb.mins.x + offset.x,
+                        aabb.mins.y + offset.y,
+                        aabb.maxs.x + offset.x,
+                        aabb.maxs.y + offset.y,
+                    ))
+                }
+                PrefabPartProperties::Pickup { .. } => None,
+            })
+            .reduce(|a, b| a.union(b))
+    }
+}
+
+/// Spawns all parts of the prefab under a single `Enemy` root. `height` is the height of the root
+/// above the ground
+pub fn spawn_prefab(
+    commands: &mut Commands,
+    prefab: &PrefabProperties,
+    models: &Models,
+    x: f32,
+    height: f32,
+) -> Entity {
+    let enemy = EnemyBundle {
+        kind: EnemyKind(prefab.name.clone()),
+        ..Default::default()
+    };
+    let mut root = commands.spawn(enemy);
+    // The parts are placed relative to each other. Don't let the group fall apart or drop
+    root.insert(RigidBody::KinematicPositionBased)
+        .insert(SpatialBundle::from_transform(Transform::from_xyz(
+            x, height, 0.0,
+        )))
+        .insert(Name::new(prefab.name.clone()));
+
+    root.with_children(|parent| {
+        for part in &prefab.parts {
+            match part {
+                PrefabPartProperties::Model {
+                    model,
+                    offset,
+                    harmful,
+                } => {
+                    let Some(model) = models.models.get(model) else {
+                        warn!("{}: Unknown model {}", prefab.name, model);
+                        continue;
+                    };
+                    let mut model = model.clone();
+                    // The root moves the whole prefab
+                    model.physics = None;
+                    model.behavior = None;
+                    model.spritesheet.transform.translation += offset.extend(0.0);
+                    // Harmless parts are solid like the ground
+                    model.set_collision_layer(if *harmful {
+                        CollisionGroup::Enemy
+                    } else {
+                        CollisionGroup::Wall
+                    });
+                    model.spawn(parent.spawn(PrefabPart { harmful: *harmful }));
+                }
+                PrefabPartProperties::Collider { collider, offset } => {
+                    parent
+                        .spawn(Collider::from(collider.clone()))
+                        .insert(TransformBundle::from_transform(
+                            Transform::from_translation(offset.extend(0.0)),
+                        ))
+                        .insert(CollisionGroup::Wall.collision_groups())
+                        .insert(PrefabPart { harmful: false })
+                        .insert(ActiveEvents::COLLISION_EVENTS);
+                }
+                PrefabPartProperties::Pickup {
+                    model,
+                    offset,
+                    score,
+                } => {
+                    let Some(model) = models.models.get(model) else {
+                        warn!("{}: Unknown model {}", prefab.name, model);
+                        continue;
+                    };
+                    let mut model = model.clone();
+                    model.physics = None;
+                    model.behavior = None;
+                    model.spritesheet.transform.translation += offset.extend(0.0);
+                    model.set_collision_layer(CollisionGroup::Pickup);
+                    // Only detect the contact without pushing the player
+                    model.map_colliders(|collider| {
+                        collider.solver_group = SolverGroups::new(Group::NONE, Group::NONE)
+                    });
+                    model.spawn(
+                        parent.spawn((PrefabPart { harmful: false }, Pickup { score: *score })),
+                    );
+                }
+            }
+        }
+    });
+    root.id()
+}
+
+#[derive(Resource, Default)]
+pub struct PrefabHandles {
+    prefabs: Handle<PrefabList>,
+    props: Handle<CharacterProperties>,
+}
+
+fn load_assets(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(PrefabHandles {
+        prefabs: asset_server.load("obstacles.prefabs.yaml"),
+        props: asset_server.load("props.yaml"),
+    });
+}
+
+/// Props are models that are never spawned on their own like parts of prefabs or projectiles
+fn setup_props(
+    mut models: ResMut<Models>,
+    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+    asset_server: Res<AssetServer>,
+    mut prop_assets: ResMut<Assets<CharacterProperties>>,
+    aseprite_sheets: Res<Assets<AsepriteSheet>>,
+    handles: Res<PrefabHandles>,
+) {
+    if let Some(props) = CharacterProperties::take_loaded(
+        &mut prop_assets,
+        &handles.props,
+        &asset_server,
+        &aseprite_sheets,
+    ) {
+        for prop in &props.characters {
+            if let Some(model) =
+                prop.get_model(&asset_server, &mut texture_atlas_layouts, &aseprite_sheets)
+            {
+                models.models.insert(prop.name.clone(), model);
+            }
+        }
+    }
+}
+
+fn setup_prefabs(
+    mut prefabs: ResMut<Prefabs>,
+    mut spawn_table: ResMut<SpawnTable>,
+    mut prefab_assets: ResMut<Assets<PrefabList>>,
+    handles: Res<PrefabHandles>,
+) {
+    if let Some(prefab_list) = prefab_assets.remove(handles.prefabs.id()) {
+        info!("Setting up prefabs!");
+        for prefab in prefab_list.prefabs {
+            spawn_table
+                .entries
+                .insert(prefab.name.clone(), prefab.spawn.clone());
+            prefabs.prefabs.insert(prefab.name.clone(), prefab);
+        }
+    }
+}
+
+fn collect_pickups(
+    mut commands: Commands,
+    mut er_pickup: EventReader<PickupCollected>,
+    mut progress: ResMut<RunProgress>,
+    q_pickups: Query<&Pickup>,
+) {
+    let mut collected = vec![];
+    for contact in er_pickup.read().filter(|contact| contact.started()) {
+        // Multiple colliders of the player can touch the same pickup
+        if collected.contains(&contact.b) {
+            continue;
+        }
+        let Ok(pickup) = q_pickups.get(contact.b) else {
+            continue;
+        };
+        collected.push(contact.b);
+        progress.score += pickup.score;
+        commands.entity(contact.b).despawn_recursive();
+    }
+}
+
+pub struct PrefabPlugin;
+
+impl Plugin for PrefabPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_assets)
+            .add_systems(Update, (setup_props, setup_prefabs))
+            .add_systems(Update, collect_pickups.run_if(in_state(GameState::Running)))
+            .add_contact_pair::<Player, Pickup>()
+            .init_resource::<PrefabHandles>()
+            .init_resource::<Prefabs>();
+    }
+}
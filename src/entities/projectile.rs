@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_rapier2d::{
+    dynamics::{GravityScale, LockedAxes, RigidBody, Velocity},
+    geometry::{Collider, Group, SolverGroups},
+};
+use leafwing_input_manager::action_state::ActionState;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    collision::{ColliderOwner, Contact, ContactAppExt},
+    input::PlayerAction,
+    model::Models,
+    physics::CollisionGroup,
+    AppState, GameState,
+};
+
+use super::{
+    defeat::{DefeatCause, Defeatable, EnemyDefeatEvent},
+    enemy::Enemy,
+    player::Player,
+};
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(tag = "type")]
+pub enum Trajectory {
+    #[serde(rename = "straight")]
+    Straight,
+    /// Thrown upwards and pulled down by gravity
+    #[serde(rename = "arc")]
+    Arc { lift: f32, gravity_scale: f32 },
+    /// Turns towards the closest target
+    #[serde(rename = "homing")]
+    Homing {
+        /// Radians per second
+        turn_rate: f32,
+    },
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ProjectileProperties {
+    pub name: String,
+    /// Model from the props or boss characters
+    pub model: String,
+    pub speed: f32,
+    pub trajectory: Trajectory,
+    /// Seconds until the projectile disappears
+    pub lifetime: f32,
+}
+
+#[derive(Deserialize, Serialize, Asset, TypePath)]
+pub struct ProjectileList {
+    pub projectiles: Vec<ProjectileProperties>,
+}
+
+#[derive(Resource, Default)]
+pub struct Projectiles {
+    pub projectiles: HashMap<String, ProjectileProperties>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProjectileOwner {
+    Player,
+    Enemy,
+}
+
+impl ProjectileOwner {
+    /// Only hits the other side and walls
+    fn collision_layer(&self) -> CollisionGroup {
+        match self {
+            Self::Player => CollisionGroup::PlayerProjectile,
+            Self::Enemy => CollisionGroup::EnemyProjectile,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Projectile {
+    pub owner: ProjectileOwner,
+    /// Entity that fired the projectile
+    pub shooter: Entity,
+    pub trajectory: Trajectory,
+    pub speed: f32,
+    pub lifetime: Timer,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct WeaponProperties {
+    pub projectile: String,
+    /// Seconds between two shots
+    pub cooldown: f32,
+    /// Spawn position relative to the shooter
+    pub offset: Vec2,
+}
+
+impl Default for WeaponProperties {
+    fn default() -> Self {
+        Self {
+            projectile: String::new(),
+            cooldown: 1.0,
+            offset: Vec2::ZERO,
+        }
+    }
+}
+
+/// Fires projectiles. The player fires with the `Shoot` action, enemies whenever the weapon is
+/// ready
+#[derive(Component, Clone)]
+pub struct Weapon {
+    pub properties: WeaponProperties,
+    pub cooldown: Timer,
+}
+
+impl From<&WeaponProperties> for Weapon {
+    fn from(value: &WeaponProperties) -> Self {
+        Self {
+            properties: value.clone(),
+            cooldown: Timer::from_seconds(value.cooldown, TimerMode::Once),
+        }
+    }
+}
+
+pub fn spawn_projectile(
+    commands: &mut Commands,
+    projectile: &ProjectileProperties,
+    models: &Models,
+    owner: ProjectileOwner,
+    shooter: Entity,
+    position: Vec2,
+    direction: Vec2,
+) -> Option<Entity> {
+    let Some(model) = models.models.get(&projectile.model) else {
+        warn!("{}: Unknown model {}", projectile.name, projectile.model);
+        return None;
+    };
+    let mut model = model.clone();
+    model.physics = None;
+    model.behavior = None;
+    model.spritesheet.transform.translation = position.extend(0.0);
+    model.spritesheet.sprite.flip_x = direction.x < 0.0;
+    // The owner decides what can be hit
+    model.collision_groups = None;
+    model.set_collision_layer(owner.collision_layer());
+    // Hits are only reported. The projectile doesn't push anything
+    model.map_colliders(|collider| {
+        collider.solver_group = SolverGroups::new(Group::NONE, Group::NONE)
+    });
+    let mut velocity = direction.normalize_or_zero() * projectile.speed;
+    let gravity_scale = match projectile.trajectory {
+        Trajectory::Arc {
+            lift,
+            gravity_scale,
+        } => {
+            velocity.y += lift;
+            gravity_scale
+        }
+        _ => 0.0,
+    };
+    let mut entity_commands = commands.spawn((
+        Projectile {
+            owner,
+            shooter,
+            trajectory: projectile.trajectory.clone(),
+            speed: projectile.speed,
+            lifetime: Timer::from_seconds(projectile.lifetime, TimerMode::Once),
+        },
+        RigidBody::Dynamic,
+        Velocity::linear(velocity),
+        GravityScale(gravity_scale),
+        LockedAxes::ROTATION_LOCKED,
+        Name::new(projectile.name.clone()),
+    ));
+    let entity = entity_commands.id();
+    model.spawn(entity_commands);
+    Some(entity)
+}
+
+fn fire_weapons(
+    mut commands: Commands,
+    time: Res<Time<Virtual>>,
+    projectiles: Res<Projectiles>,
+    models: Res<Models>,
+    mut q_weapons: Query<(
+        Entity,
+        &mut Weapon,
+        &Transform,
+        Option<&ActionState<PlayerAction>>,
+        Has<Player>,
+    )>,
+) {
+    for (entity, mut weapon, transform, input, is_player) in &mut q_weapons {
+        weapon.cooldown.tick(time.delta());
+        if !weapon.cooldown.finished() {
+            continue;
+        }
+        let (owner, direction) = if is_player {
+            if !input.is_some_and(|input| input.pressed(&PlayerAction::Shoot)) {
+                continue;
+            }
+            (ProjectileOwner::Player, Vec2::X)
+        } else {
+            (ProjectileOwner::Enemy, Vec2::NEG_X)
+        };
+        let Some(projectile) = projectiles.projectiles.get(&weapon.properties.projectile) else {
+            continue;
+        };
+        let offset = weapon.properties.offset * Vec2::new(direction.x, 1.0);
+        spawn_projectile(
+            &mut commands,
+            projectile,
+            &models,
+            owner,
+            entity,
+            transform.translation.xy() + offset,
+            direction,
+        );
+        weapon.cooldown.reset();
+    }
+}
+
+fn steer_projectiles(
+    time: Res<Time<Virtual>>,
+    mut q_projectiles: Query<(&Projectile, &Transform, &mut Velocity)>,
+    q_player: Query<&Transform, With<Player>>,
+    q_enemies: Query<&Transform, With<Enemy>>,
+) {
+    for (projectile, transform, mut velocity) in &mut q_projectiles {
+        let Trajectory::Homing { turn_rate } = projectile.trajectory else {
+            continue;
+        };
+        let position = transform.translation.xy();
+        let target = match projectile.owner {
+            ProjectileOwner::Enemy => q_player.get_single().ok(),
+            // Only targets in front of the projectile
+            ProjectileOwner::Player => q_enemies
+                .iter()
+                .filter(|enemy| enemy.translation.x > position.x)
+                .min_by(|a, b| {
+                    let a = a.translation.xy().distance_squared(position);
+                    let b = b.translation.xy().distance_squared(position);
+                    a.total_cmp(&b)
+                }),
+        };
+        let Some(target) = target else {
+            continue;
+        };
+        let current = velocity.linvel.normalize_or_zero();
+        let wanted = (target.translation.xy() - position).normalize_or_zero();
+        let max_turn = turn_rate * time.delta_seconds();
+        let angle = current.angle_between(wanted).clamp(-max_turn, max_turn);
+        velocity.linvel = Vec2::from_angle(angle).rotate(current) * projectile.speed;
+    }
+}
+
+fn expire_projectiles(
+    mut commands: Commands,
+    time: Res<Time<Virtual>>,
+    mut q_projectiles: Query<(Entity, &mut Projectile)>,
+) {
+    for (entity, mut projectile) in &mut q_projectiles {
+        if projectile.lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+pub type ProjectileHit = Contact<Projectile, Collider>;
+
+/// Projectiles disappear on the first hit. Hits of the player are handled by the player
+fn projectile_collision(
+    mut commands: Commands,
+    mut er_hit: EventReader<ProjectileHit>,
+    q_projectiles: Query<&Projectile>,
+    defeatable_owner: ColliderOwner<Defeatable>,
+    q_defeatable: Query<&Defeatable>,
+    mut ew_defeat: EventWriter<EnemyDefeatEvent>,
+) {
+    let mut hit = vec![];
+    for contact in er_hit.read().filter(|contact| contact.started()) {
+        if hit.contains(&contact.a) {
+            continue;
+        }
+        let Ok(projectile) = q_projectiles.get(contact.a) else {
+            continue;
+        };
+        hit.push(contact.a);
+        if projectile.owner == ProjectileOwner::Player {
+            let target = defeatable_owner.get(contact.b_collider).filter(|enemy| {
+                q_defeatable
+                    .get(*enemy)
+                    .is_ok_and(|defeatable| defeatable.0.projectile)
+            });
+            if let Some(enemy) = target {
+                ew_defeat.send(EnemyDefeatEvent {
+                    player: projectile.shooter,
+                    enemy,
+                    cause: DefeatCause::Projectile,
+                });
+            }
+        }
+        commands.entity(contact.a).despawn_recursive();
+    }
+}
+
+fn cleanup_projectiles(mut commands: Commands, q_projectiles: Query<Entity, With<Projectile>>) {
+    for entity in &q_projectiles {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ProjectileHandle(Handle<ProjectileList>);
+
+fn load_assets(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(ProjectileHandle(asset_server.load("projectiles.yaml")));
+}
+
+fn setup_projectiles(
+    mut projectiles: ResMut<Projectiles>,
+    mut projectile_assets: ResMut<Assets<ProjectileList>>,
+    handle: Res<ProjectileHandle>,
+) {
+    if let Some(list) = projectile_assets.remove(handle.0.id()) {
+        info!("Loaded {} projectiles", list.projectiles.len());
+        for projectile in list.projectiles {
+            projectiles
+                .projectiles
+                .insert(projectile.name.clone(), projectile);
+        }
+    }
+}
+
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_assets)
+            .add_systems(Update, setup_projectiles)
+            .add_systems(
+                Update,
+                (
+                    fire_weapons,
+                    steer_projectiles,
+                    expire_projectiles,
+                    projectile_collision,
+                )
+                    .run_if(in_state(GameState::Running))
+                    .run_if(in_state(AppState::Game)),
+            )
+            .add_systems(OnEnter(AppState::Game), cleanup_projectiles)
+            .add_contact_pair::<Projectile, Collider>()
+            .init_resource::<ProjectileHandle>()
+            .init_resource::<Projectiles>();
+    }
+}
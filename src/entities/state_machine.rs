@@ -0,0 +1,200 @@
+use bevy::prelude::*;
+use bevy_2d_animations::{Animated, AnimatedSprite};
+use bevy_rapier2d::dynamics::Velocity;
+use leafwing_input_manager::action_state::ActionState;
+use serde::{Deserialize, Serialize};
+
+use crate::input::PlayerAction;
+
+use super::player::{AirState, Dashing, PitFall, Player};
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(tag = "type")]
+pub enum TransitionCondition {
+    /// Characters without a ground check always count as grounded
+    #[serde(rename = "grounded")]
+    Grounded,
+    #[serde(rename = "airborne")]
+    Airborne,
+    /// Falling into a pit
+    #[serde(rename = "pit_fall")]
+    PitFall,
+    /// Rising from an extra jump
+    #[serde(rename = "air_jump")]
+    AirJump,
+    #[serde(rename = "fast_fall")]
+    FastFall,
+    #[serde(rename = "glide")]
+    Glide,
+    #[serde(rename = "dashing")]
+    Dashing,
+    #[serde(rename = "not")]
+    Not { condition: Box<TransitionCondition> },
+    #[serde(rename = "velocity_y_above")]
+    VelocityYAbove { value: f32 },
+    #[serde(rename = "velocity_y_below")]
+    VelocityYBelow { value: f32 },
+    #[serde(rename = "action_pressed")]
+    ActionPressed { action: PlayerAction },
+    #[serde(rename = "action_released")]
+    ActionReleased { action: PlayerAction },
+    /// Seconds since the state was entered
+    #[serde(rename = "time_in_state")]
+    TimeInState { value: f32 },
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct TransitionProperties {
+    pub to: String,
+    /// All conditions have to be met
+    #[serde(default)]
+    pub conditions: Vec<TransitionCondition>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct StateProperties {
+    pub name: String,
+    pub animation: String,
+    #[serde(default = "default_looping")]
+    pub looping: bool,
+    /// Checked in order. The first matching transition is taken
+    #[serde(default)]
+    pub transitions: Vec<TransitionProperties>,
+}
+
+fn default_looping() -> bool {
+    true
+}
+
+#[derive(Deserialize, Serialize, Clone, Component)]
+pub struct AnimationStateMachine {
+    /// Defaults to the first state
+    #[serde(default)]
+    pub initial: Option<String>,
+    pub states: Vec<StateProperties>,
+}
+
+/// Current state of the `AnimationStateMachine`
+#[derive(Component, Default, Debug)]
+pub struct CharacterAnimationState {
+    /// None until the initial state was entered
+    pub state: Option<usize>,
+    pub time_in_state: f32,
+}
+
+impl AnimationStateMachine {
+    fn state_index(&self, name: &str) -> Option<usize> {
+        self.states.iter().position(|state| state.name == name)
+    }
+
+    fn initial_state(&self) -> Option<usize> {
+        match &self.initial {
+            Some(name) => self.state_index(name),
+            None => (!self.states.is_empty()).then_some(0),
+        }
+    }
+}
+
+struct ConditionContext<'a> {
+    grounded: bool,
+    pit_fall: bool,
+    air: Option<&'a AirState>,
+    dashing: bool,
+    velocity: Vec2,
+    input: Option<&'a ActionState<PlayerAction>>,
+    time_in_state: f32,
+}
+
+impl TransitionCondition {
+    fn is_met(&self, context: &ConditionContext) -> bool {
+        match self {
+            Self::Grounded => context.grounded,
+            Self::Airborne => !context.grounded,
+            Self::PitFall => context.pit_fall,
+            Self::AirJump => context.air.is_some_and(|air| air.air_jumping),
+            Self::FastFall => context.air.is_some_and(|air| air.fast_falling),
+            Self::Glide => context.air.is_some_and(|air| air.gliding),
+            Self::Dashing => context.dashing,
+            Self::Not { condition } => !condition.is_met(context),
+            Self::VelocityYAbove { value } => context.velocity.y > *value,
+            Self::VelocityYBelow { value } => context.velocity.y < *value,
+            Self::ActionPressed { action } => context
+                .input
+                .map(|input| input.pressed(action))
+                .unwrap_or(false),
+            Self::ActionReleased { action } => context
+                .input
+                .map(|input| !input.pressed(action))
+                .unwrap_or(true),
+            Self::TimeInState { value } => context.time_in_state >= *value,
+        }
+    }
+}
+
+fn update_state_machine(
+    time: Res<Time<Virtual>>,
+    mut q_characters: Query<(
+        &AnimationStateMachine,
+        &mut CharacterAnimationState,
+        &mut AnimatedSprite,
+        Option<&Velocity>,
+        Option<&Player>,
+        Option<&ActionState<PlayerAction>>,
+        Has<PitFall>,
+        Option<&AirState>,
+        Has<Dashing>,
+    )>,
+) {
+    for (
+        state_machine,
+        mut current,
+        mut animation,
+        velocity,
+        player,
+        input,
+        pit_fall,
+        air,
+        dashing,
+    ) in &mut q_characters
+    {
+        let next_state = match current.state {
+            None => state_machine.initial_state(),
+            Some(state_idx) => {
+                current.time_in_state += time.delta_seconds();
+                let context = ConditionContext {
+                    grounded: player.map(|player| player.is_grounded).unwrap_or(true),
+                    pit_fall,
+                    air,
+                    dashing,
+                    velocity: velocity.map(|velocity| velocity.linvel).unwrap_or_default(),
+                    input,
+                    time_in_state: current.time_in_state,
+                };
+                state_machine.states[state_idx]
+                    .transitions
+                    .iter()
+                    .find(|transition| {
+                        transition
+                            .conditions
+                            .iter()
+                            .all(|condition| condition.is_met(&context))
+                    })
+                    .and_then(|transition| state_machine.state_index(&transition.to))
+            }
+        };
+        if let Some(next_state) = next_state {
+            let state = &state_machine.states[next_state];
+            current.state = Some(next_state);
+            current.time_in_state = 0.0;
+            animation.queue_animation(&state.animation, state.looping, None);
+        }
+    }
+}
+
+pub struct StateMachinePlugin;
+
+impl Plugin for StateMachinePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PostUpdate, update_state_machine);
+    }
+}
@@ -0,0 +1,391 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use platform::{end_drop_through, Platform};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    boss::{BossEncounter, Bosses},
+    config::GameConfig,
+    physics::RigidBodyBundle,
+    spawner::pattern::Patterns,
+    AppState, GameState, RunProgress,
+};
+
+pub mod platform;
+
+/// Ground generated beyond the farthest spawn for the width of the enemies spawned there
+const GENERATE_MARGIN: f32 = 100.0;
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub struct TileProperties {
+    /// Top left corner in the tileset
+    pub position: Vec2,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(tag = "type")]
+pub enum ChunkPart {
+    /// Solid ground. Raised by `height` tiles to build steps
+    #[serde(rename = "ground")]
+    Ground {
+        width: u32,
+        #[serde(default)]
+        height: u32,
+    },
+    #[serde(rename = "pit")]
+    Pit { width: u32 },
+    /// Floats `height` tiles above the ground. Doesn't take up any width of the chunk
+    #[serde(rename = "platform")]
+    Platform {
+        /// Tiles from the start of the chunk
+        offset: u32,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl ChunkPart {
+    fn width(&self) -> u32 {
+        match self {
+            Self::Ground { width, .. } | Self::Pit { width } => *width,
+            Self::Platform { .. } => 0,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct ChunkProperties {
+    pub name: String,
+    /// Relative chance of being picked among all allowed chunks
+    pub weight: f32,
+    /// Distance of the run before the chunk can appear
+    pub min_distance: f32,
+    pub parts: Vec<ChunkPart>,
+}
+
+impl Default for ChunkProperties {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            weight: 1.0,
+            min_distance: 0.0,
+            parts: vec![],
+        }
+    }
+}
+
+impl ChunkProperties {
+    /// Width in tiles
+    pub fn width(&self) -> u32 {
+        self.parts.iter().map(ChunkPart::width).sum()
+    }
+}
+
+#[derive(Deserialize, Serialize, Asset, TypePath, Resource, Clone)]
+#[serde(default)]
+pub struct GroundProperties {
+    pub file_path: String,
+    pub tile_size: f32,
+    /// Tiles of ground below the surface
+    pub depth: u32,
+    pub surface: TileProperties,
+    pub fill: TileProperties,
+    pub platform: TileProperties,
+    pub chunks: Vec<ChunkProperties>,
+    #[serde(skip)]
+    pub texture: Handle<Image>,
+}
+
+impl Default for GroundProperties {
+    fn default() -> Self {
+        Self {
+            file_path: String::new(),
+            tile_size: 16.0,
+            depth: 2,
+            surface: TileProperties {
+                position: Vec2::ZERO,
+            },
+            fill: TileProperties {
+                position: Vec2::ZERO,
+            },
+            platform: TileProperties {
+                position: Vec2::ZERO,
+            },
+            chunks: vec![],
+            texture: Handle::default(),
+        }
+    }
+}
+
+impl GroundProperties {
+    /// Plain ground used when no chunk is allowed yet
+    fn fallback_chunk() -> ChunkProperties {
+        ChunkProperties {
+            name: "flat".to_string(),
+            parts: vec![ChunkPart::Ground {
+                width: 16,
+                height: 0,
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn sprite(&self, tile: TileProperties, position: Vec2) -> SpriteBundle {
+        SpriteBundle {
+            texture: self.texture.clone(),
+            sprite: Sprite {
+                rect: Some(Rect::from_corners(
+                    tile.position,
+                    tile.position + Vec2::splat(self.tile_size),
+                )),
+                ..Default::default()
+            },
+            // Behind characters and props
+            transform: Transform::from_translation(position.extend(-1.0)),
+            ..Default::default()
+        }
+    }
+}
+
+/// Solid ground of a chunk in world coordinates
+#[derive(Clone, Copy)]
+pub struct SurfaceSegment {
+    pub start_x: f32,
+    pub end_x: f32,
+    pub height: f32,
+}
+
+/// Segment of the ground. Removed once the run has passed it
+#[derive(Component)]
+pub struct GroundChunk {
+    pub end_x: f32,
+    /// Pits are left out
+    pub surface: Vec<SurfaceSegment>,
+    /// Start and end of every pit
+    pub pits: Vec<(f32, f32)>,
+}
+
+/// Height of the ground at `x`. `None` above pits and where no ground has been generated yet
+pub fn surface_height<'a>(
+    chunks: impl IntoIterator<Item = &'a GroundChunk>,
+    x: f32,
+) -> Option<f32> {
+    chunks
+        .into_iter()
+        .flat_map(|chunk| &chunk.surface)
+        .find(|segment| segment.start_x <= x && x < segment.end_x)
+        .map(|segment| segment.height)
+}
+
+#[derive(Resource, Default)]
+pub struct GroundGenerator {
+    /// Start of the next chunk
+    pub next_x: f32,
+}
+
+fn spawn_chunk(
+    commands: &mut Commands,
+    ground: &GroundProperties,
+    chunk: &ChunkProperties,
+    x: f32,
+) {
+    let tile = ground.tile_size;
+    let width = chunk.width() as f32 * tile;
+    let mut surface = vec![];
+    let mut pits = vec![];
+    let mut column = 0;
+    for part in &chunk.parts {
+        let start_x = x + column as f32 * tile;
+        let end_x = x + (column + part.width()) as f32 * tile;
+        match part {
+            ChunkPart::Ground { height, .. } => surface.push(SurfaceSegment {
+                start_x,
+                end_x,
+                height: *height as f32 * tile,
+            }),
+            ChunkPart::Pit { .. } => pits.push((start_x, end_x)),
+            ChunkPart::Platform { .. } => (),
+        }
+        column += part.width();
+    }
+    commands
+        .spawn(SpatialBundle::from_transform(Transform::from_xyz(
+            x, 0.0, 0.0,
+        )))
+        .insert(GroundChunk {
+            end_x: x + width,
+            surface,
+            pits,
+        })
+        .insert(Name::new(format!("Ground {}", chunk.name)))
+        .with_children(|parent| {
+            let mut column = 0;
+            for part in &chunk.parts {
+                match part {
+                    ChunkPart::Ground { width, height } => {
+                        let rows = height + ground.depth;
+                        let size = Vec2::new(*width as f32, rows as f32) * tile;
+                        // The surface of flat ground is at zero
+                        let top = *height as f32 * tile;
+                        let left = column as f32 * tile;
+                        parent
+                            .spawn(RigidBodyBundle {
+                                body: RigidBody::Fixed,
+                                collider: Collider::cuboid(size.x / 2.0, size.y / 2.0),
+                                ..Default::default()
+                            })
+                            .insert(TransformBundle::from_transform(Transform::from_xyz(
+                                left + size.x / 2.0,
+                                top - size.y / 2.0,
+                                0.0,
+                            )));
+                        for x in 0..*width {
+                            for y in 0..rows {
+                                let tile_props = if y == 0 { ground.surface } else { ground.fill };
+                                let position = Vec2::new(
+                                    left + (x as f32 + 0.5) * tile,
+                                    top - (y as f32 + 0.5) * tile,
+                                );
+                                parent.spawn(ground.sprite(tile_props, position));
+                            }
+                        }
+                    }
+                    ChunkPart::Pit { .. } => (),
+                    ChunkPart::Platform {
+                        offset,
+                        width,
+                        height,
+                    } => {
+                        let left = *offset as f32 * tile;
+                        let top = *height as f32 * tile;
+                        let half_width = *width as f32 * tile / 2.0;
+                        parent
+                            .spawn(RigidBodyBundle {
+                                body: RigidBody::Fixed,
+                                collider: Collider::cuboid(half_width, tile / 4.0),
+                                ..Default::default()
+                            })
+                            .insert(TransformBundle::from_transform(Transform::from_xyz(
+                                left + half_width,
+                                top - tile / 4.0,
+                                0.0,
+                            )))
+                            .insert(Platform)
+                            .insert(ActiveHooks::MODIFY_SOLVER_CONTACTS);
+                        for x in 0..*width {
+                            let position =
+                                Vec2::new(left + (x as f32 + 0.5) * tile, top - tile / 2.0);
+                            parent.spawn(ground.sprite(ground.platform, position));
+                        }
+                    }
+                }
+                column += part.width();
+            }
+        });
+}
+
+fn generate_ground(
+    mut commands: Commands,
+    mut generator: ResMut<GroundGenerator>,
+    ground: Res<GroundProperties>,
+    progress: Res<RunProgress>,
+    encounter: Res<BossEncounter>,
+    bosses: Res<Bosses>,
+    config: Res<GameConfig>,
+    patterns: Res<Patterns>,
+) {
+    let arena_start = encounter.arena_start(&bosses);
+    // Covers the spawn point and the patterns spawned behind it
+    let generate_ahead = config.spawn_x + patterns.max_length() + GENERATE_MARGIN;
+    while generator.next_x < progress.distance + generate_ahead {
+        let x = generator.next_x;
+        let allowed: Vec<&ChunkProperties> = ground
+            .chunks
+            .iter()
+            .filter(|chunk| chunk.min_distance <= x && chunk.width() > 0)
+            // Bosses can't handle pits and steps. Only flat ground reaches into their arena
+            .filter(|chunk| {
+                arena_start.map_or(true, |start| {
+                    x + chunk.width() as f32 * ground.tile_size <= start
+                })
+            })
+            .collect();
+        let chunk = allowed
+            .choose_weighted(&mut rand::thread_rng(), |chunk| chunk.weight)
+            .map(|chunk| (*chunk).clone())
+            .unwrap_or_else(|_| GroundProperties::fallback_chunk());
+        spawn_chunk(&mut commands, &ground, &chunk, x);
+        generator.next_x += chunk.width() as f32 * ground.tile_size;
+    }
+}
+
+fn remove_ground(
+    mut commands: Commands,
+    progress: Res<RunProgress>,
+    config: Res<GameConfig>,
+    q_chunks: Query<(Entity, &GroundChunk)>,
+) {
+    // Enemies need ground to stand on until they reach the despawner
+    for (entity, chunk) in &q_chunks {
+        if chunk.end_x < progress.distance + config.despawner_x {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn reset_ground(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    q_chunks: Query<Entity, With<GroundChunk>>,
+) {
+    for entity in &q_chunks {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.insert_resource(GroundGenerator {
+        next_x: config.despawner_x,
+    });
+}
+
+#[derive(Resource, Default)]
+pub struct GroundHandle(Handle<GroundProperties>);
+
+fn load_assets(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(GroundHandle(asset_server.load("level.ground.yaml")));
+}
+
+fn setup_ground(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut ground_assets: ResMut<Assets<GroundProperties>>,
+    handle: Res<GroundHandle>,
+) {
+    if let Some(mut ground) = ground_assets.remove(handle.0.id()) {
+        info!("Loaded {} ground chunks", ground.chunks.len());
+        ground.texture = asset_server.load(&ground.file_path);
+        commands.insert_resource(ground);
+    }
+}
+
+pub struct GroundPlugin;
+
+impl Plugin for GroundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_assets)
+            .add_systems(Update, setup_ground)
+            .add_systems(
+                Update,
+                (
+                    generate_ground.run_if(resource_exists::<GroundProperties>),
+                    remove_ground,
+                    end_drop_through,
+                )
+                    .run_if(in_state(GameState::Running))
+                    .run_if(in_state(AppState::Game)),
+            )
+            .add_systems(OnEnter(AppState::Game), reset_ground)
+            .init_resource::<GroundGenerator>()
+            .init_resource::<GroundHandle>();
+    }
+}
@@ -0,0 +1,66 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_rapier2d::{
+    pipeline::{BevyPhysicsHooks, ContactModificationContextView},
+    rapier::math::Vector,
+};
+
+use crate::collision::ColliderOwner;
+
+/// Seconds platforms stay passable after dropping through
+const DROP_THROUGH_TIME: f32 = 0.3;
+/// Radians the contact normal may differ from straight up
+const ALLOWED_ANGLE: f32 = 0.1;
+
+/// Floating platform of a chunk. Only solid from above
+#[derive(Component, Default)]
+pub struct Platform;
+
+/// Present while the character falls through platforms
+#[derive(Component)]
+pub struct DropThrough(pub Timer);
+
+impl Default for DropThrough {
+    fn default() -> Self {
+        Self(Timer::from_seconds(DROP_THROUGH_TIME, TimerMode::Once))
+    }
+}
+
+/// Contact modification for platforms. Needs `ActiveHooks::MODIFY_SOLVER_CONTACTS` on the
+/// platform collider
+#[derive(SystemParam)]
+pub struct PlatformHooks<'w, 's> {
+    q_platforms: Query<'w, 's, (), With<Platform>>,
+    dropping: ColliderOwner<'w, 's, DropThrough>,
+}
+
+impl BevyPhysicsHooks for PlatformHooks<'_, '_> {
+    fn modify_solver_contacts(&self, mut context: ContactModificationContextView) {
+        // Normal pointing out of the platform in the space of the first collider
+        let (other, allowed_normal) = if self.q_platforms.contains(context.collider1()) {
+            (context.collider2(), Vector::y())
+        } else if self.q_platforms.contains(context.collider2()) {
+            (context.collider1(), -Vector::y())
+        } else {
+            return;
+        };
+        if self.dropping.get(other).is_some() {
+            context.raw.solver_contacts.clear();
+        } else {
+            context
+                .raw
+                .update_as_oneway_platform(&allowed_normal, ALLOWED_ANGLE);
+        }
+    }
+}
+
+pub fn end_drop_through(
+    mut commands: Commands,
+    time: Res<Time<Virtual>>,
+    mut q_dropping: Query<(Entity, &mut DropThrough)>,
+) {
+    for (entity, mut drop_through) in &mut q_dropping {
+        if drop_through.0.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<DropThrough>();
+        }
+    }
+}
@@ -1,11 +1,14 @@
 use bevy::reflect::Reflect;
 use leafwing_input_manager::Actionlike;
+use serde::{Deserialize, Serialize};
 
-#[derive(Actionlike, PartialEq, Eq, Hash, Clone, Copy, Debug, Reflect)]
+#[derive(Actionlike, PartialEq, Eq, Hash, Clone, Copy, Debug, Reflect, Serialize, Deserialize)]
 pub enum PlayerAction {
     // Player Character
     Jump,
     Duck,
+    Shoot,
+    Dash,
 
     // System
     Pause,
@@ -1,3 +1,4 @@
+use aseprite::AsepriteSheet;
 use bevy::{
     asset::AssetMetaCheck,
     prelude::*,
@@ -5,38 +6,52 @@ use bevy::{
     window::{PrimaryWindow, WindowResized, WindowResolution},
 };
 use bevy_2d_animations::AnimationPlugin;
-use bevy_common_assets::yaml::YamlAssetPlugin;
+use bevy_common_assets::{json::JsonAssetPlugin, yaml::YamlAssetPlugin};
+use boss::{BossPlugin, Bosses};
+use collision::{Contact, ContactAppExt};
+use config::{ConfigPlugin, GameConfig};
+use ground::{platform::PlatformHooks, GroundPlugin, GroundProperties};
 
 use bevy_parallax::{
     CreateParallaxEvent, LayerData, LayerRepeat, LayerSpeed, ParallaxCameraComponent,
     ParallaxMoveEvent, ParallaxPlugin, RepeatStrategy,
 };
 use bevy_rapier2d::{
-    dynamics::RigidBody,
-    geometry::{ActiveEvents, Collider, CollisionGroups, Sensor},
-    pipeline::CollisionEvent,
-    plugin::{NoUserData, RapierConfiguration, RapierPhysicsPlugin},
+    geometry::{ActiveCollisionTypes, ActiveEvents, Collider, CollisionGroups, Sensor},
+    plugin::{RapierConfiguration, RapierPhysicsPlugin},
     render::RapierDebugRenderPlugin,
 };
 use entities::{
+    behavior::BehaviorPlugin,
     character::{CharacterProperties, CharacterProperty},
+    defeat::DefeatPlugin,
     enemy::{Enemy, EnemyPlugin},
-    player::{PlayerCamera, PlayerCameraBundle, PlayerPlugin},
+    player::{DeathCause, PitFall, PlayerCamera, PlayerCameraBundle, PlayerPlugin},
+    prefab::{PrefabList, PrefabPlugin},
+    projectile::{ProjectileList, ProjectilePlugin},
+    state_machine::StateMachinePlugin,
 };
 use input::PlayerAction;
 use leafwing_input_manager::plugin::InputManagerPlugin;
-use model::Models;
-use physics::{CollisionGroup, RigidBodyBundle};
+use model::ModelPlugin;
+use physics::{CollisionGroup, GamePhysicsPlugin};
+use spawner::{pattern::Patterns, SpawnerPlugin};
 use std::vec::Vec;
 use ui::GameUiPlugin;
 
 #[cfg(debug_assertions)]
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 
+mod aseprite;
+mod boss;
+mod collision;
+mod config;
 mod entities;
+mod ground;
 mod input;
 mod model;
 mod physics;
+mod spawner;
 mod ui;
 
 #[derive(States, Default, Debug, PartialEq, Eq, Hash, Clone)]
@@ -71,6 +86,19 @@ fn handle_state_change<S: States + Clone>(
 #[derive(Event, Default)]
 pub struct ResetGameEvent;
 
+/// Progress of the current run
+#[derive(Resource, Default)]
+pub struct RunProgress {
+    /// Distance travelled by the player
+    pub distance: f32,
+    /// Seconds since the start of the run
+    pub time: f32,
+    /// Collected from pickups
+    pub score: u32,
+    /// Set once the run is over
+    pub death: Option<DeathCause>,
+}
+
 fn main() {
     let mut app = App::new();
 
@@ -82,6 +110,11 @@ fn main() {
     app.add_plugins(
         DefaultPlugins
             .set(ImagePlugin::default_nearest())
+            // Reloads the game config while tuning
+            .set(AssetPlugin {
+                watch_for_changes_override: Some(cfg!(feature = "file_watcher")),
+                ..Default::default()
+            })
             .set(WindowPlugin {
                 primary_window: Some(Window {
                     ..Default::default()
@@ -90,17 +123,39 @@ fn main() {
             }),
     )
     .add_plugins(InputManagerPlugin::<PlayerAction>::default())
-    .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0))
+    .add_plugins(RapierPhysicsPlugin::<PlatformHooks>::pixels_per_meter(
+        100.0,
+    ))
+    .add_plugins(GamePhysicsPlugin)
+    .add_plugins(ConfigPlugin)
     .add_plugins(PlayerPlugin)
     .add_plugins(EnemyPlugin)
+    .add_plugins(PrefabPlugin)
+    .add_plugins(DefeatPlugin)
+    .add_plugins(BossPlugin)
+    .add_plugins(ProjectilePlugin)
+    .add_plugins(SpawnerPlugin)
+    .add_plugins(GroundPlugin)
+    .add_plugins(StateMachinePlugin)
+    .add_plugins(BehaviorPlugin)
     .add_plugins(GameUiPlugin)
     .add_plugins(AnimationPlugin)
+    .add_plugins(ModelPlugin)
     .add_plugins(YamlAssetPlugin::<CharacterProperties>::new(&[
         "characters.yaml",
     ]))
     .add_plugins(YamlAssetPlugin::<CharacterProperty>::new(&[
         "character.yaml",
     ]))
+    .add_plugins(YamlAssetPlugin::<Patterns>::new(&["patterns.yaml"]))
+    .add_plugins(YamlAssetPlugin::<PrefabList>::new(&["prefabs.yaml"]))
+    .add_plugins(YamlAssetPlugin::<Bosses>::new(&["bosses.yaml"]))
+    .add_plugins(YamlAssetPlugin::<GameConfig>::new(&["config.yaml"]))
+    .add_plugins(YamlAssetPlugin::<GroundProperties>::new(&["ground.yaml"]))
+    .add_plugins(YamlAssetPlugin::<ProjectileList>::new(&[
+        "projectiles.yaml",
+    ]))
+    .add_plugins(JsonAssetPlugin::<AsepriteSheet>::new(&["aseprite.json"]))
     .add_plugins(ParallaxPlugin);
 
     #[cfg(debug_assertions)]
@@ -109,15 +164,23 @@ fn main() {
             .add_plugins(RapierDebugRenderPlugin::default());
     }
 
-    app.add_systems(Startup, (setup_camera, setup_background).chain())
-        .add_systems(
-            Update,
-            (update_world, collision).run_if(in_state(GameState::Running)),
+    app.add_systems(
+        Startup,
+        (setup_camera, setup_background, setup_world).chain(),
+    )
+    .add_systems(
+        Update,
+        (
+            (update_world, move_camera, follow_camera).chain(),
+            despawn_enemies,
+            despawn_fallen_enemies,
         )
-        .insert_resource(RapierConfiguration {
-            gravity: Vec2::new(0.0, -800.0),
-            ..Default::default()
-        });
+            .run_if(in_state(GameState::Running)),
+    )
+    .insert_resource(RapierConfiguration {
+        gravity: GameConfig::default().gravity,
+        ..Default::default()
+    });
 
     app.add_systems(
         Update,
@@ -133,11 +196,12 @@ fn main() {
     app.add_systems(OnEnter(GameState::Paused), pause_time);
     app.add_systems(OnEnter(GameState::Running), resume_time);
     app.add_systems(OnEnter(AppState::GameOver), handle_gameover);
-    app.add_systems(OnEnter(AppState::Game), (handle_game_start, setup_world));
+    app.add_systems(OnEnter(AppState::Game), handle_game_start);
 
     app.add_event::<ResetGameEvent>();
-
-    app.init_resource::<Models>();
+    app.add_contact_pair::<Despawner, Enemy>();
+    app.add_contact_pair::<KillPlane, Enemy>();
+    app.init_resource::<RunProgress>();
 
     app.run();
 }
@@ -145,6 +209,7 @@ fn main() {
 fn handle_game_start(mut commands: Commands, mut ew_reset: EventWriter<ResetGameEvent>) {
     // Cleanup old stuff
     ew_reset.send_default();
+    commands.insert_resource(RunProgress::default());
     commands.insert_resource(NextState(Some(GameState::Running)));
 }
 
@@ -164,20 +229,66 @@ fn pause_time(mut time: ResMut<Time<Virtual>>) {
 #[derive(Component, Default)]
 pub struct Despawner;
 
+/// Ends the run of a player falling into it
+#[derive(Component, Default)]
+pub struct KillPlane;
+
+/// Below the deepest ground tiles
+const KILL_PLANE_Y: f32 = -100.0;
+
+/// Keeps the x position relative to the player camera
+#[derive(Component, Default)]
+pub struct FollowCamera {
+    pub offset: f32,
+}
+
 fn update_world(
     time: Res<Time<Virtual>>,
-    mut q_obstacale: Query<&mut Transform, With<Enemy>>,
+    config: Res<GameConfig>,
+    mut progress: ResMut<RunProgress>,
     mut ew_parallax: EventWriter<ParallaxMoveEvent>,
     q_camera: Query<Entity, With<ParallaxCameraComponent>>,
+    q_falling: Query<(), With<PitFall>>,
 ) {
+    // The run stops while the player falls into a pit
+    if !q_falling.is_empty() {
+        return;
+    }
     let camera = q_camera.get_single().unwrap();
     ew_parallax.send(ParallaxMoveEvent {
         camera,
-        camera_move_speed: Vec2::new(1.0, 0.0) * time.delta_seconds() * 100.0,
+        camera_move_speed: Vec2::new(1.0, 0.0) * time.delta_seconds() * config.scroll_speed,
     });
-    // Scroll world
-    for mut transform in &mut q_obstacale {
-        transform.translation.x -= 100.0 * time.delta_seconds();
+    progress.distance += config.scroll_speed * time.delta_seconds();
+    progress.time += time.delta_seconds();
+}
+
+/// The camera advances with the distance of the run. The player tries to keep up with it
+fn move_camera(
+    progress: Res<RunProgress>,
+    mut q_camera: Query<(&mut Transform, &PlayerCamera)>,
+    q_falling: Query<&Transform, (With<PitFall>, Without<PlayerCamera>)>,
+) {
+    // Follows a player falling into a pit down to the kill plane
+    let fall = q_falling
+        .get_single()
+        .map(|player| player.translation.y.clamp(KILL_PLANE_Y, 0.0))
+        .unwrap_or_default();
+    for (mut transform, camera) in &mut q_camera {
+        transform.translation.x = progress.distance + camera.offset.x;
+        transform.translation.y = camera.offset.y + fall;
+    }
+}
+
+fn follow_camera(
+    q_camera: Query<&Transform, With<PlayerCamera>>,
+    mut q_followers: Query<(&mut Transform, &FollowCamera), Without<PlayerCamera>>,
+) {
+    let Ok(camera) = q_camera.get_single() else {
+        return;
+    };
+    for (mut transform, follow) in &mut q_followers {
+        transform.translation.x = camera.translation.x + follow.offset;
     }
 }
 
@@ -212,27 +323,28 @@ fn setup_background(
 fn on_resize(
     mut er_resize: EventReader<WindowResized>,
     mut q_window: Query<&mut Window, With<PrimaryWindow>>,
-    mut q_camera: Query<&mut Transform, With<PlayerCamera>>,
+    mut q_camera: Query<&mut PlayerCamera>,
 ) {
     for event in er_resize.read() {
         for mut window in &mut q_window {
             window.resolution = WindowResolution::new(event.width, event.height);
-            for mut transform in &mut q_camera {
+            for mut camera in &mut q_camera {
                 // TODO: remove fixed values
-                transform.translation.y = window.height() * 0.1;
-                transform.translation.x = window.width() * 0.1;
+                camera.offset.y = window.height() * 0.1;
+                camera.offset.x = window.width() * 0.1;
             }
         }
     }
 }
 
-fn setup_camera(mut commands: Commands) {
+fn setup_camera(mut commands: Commands, config: Res<GameConfig>) {
     // spawn camera
     let mut camera_bundle = PlayerCameraBundle::default();
-    camera_bundle.camera.projection.scale = 0.25;
+    camera_bundle.camera.projection.scale = config.camera_scale;
 
-    let mut cam_cmd = commands.spawn(camera_bundle);
-    cam_cmd.insert(Name::new("Player Camera"));
+    commands
+        .spawn(camera_bundle)
+        .insert(Name::new("Player Camera"));
 
     let mut parallax_camera = Camera2dBundle {
         camera: Camera {
@@ -241,66 +353,63 @@ fn setup_camera(mut commands: Commands) {
         },
         ..Default::default()
     };
-    parallax_camera.projection.scale = 0.25;
+    parallax_camera.projection.scale = config.camera_scale;
     // TODO: remove fixed values
     parallax_camera.transform.translation.y = -130.0;
 
-    cam_cmd.with_children(|p| {
-        p.spawn(parallax_camera)
-            .insert(ParallaxCameraComponent::new(1))
-            .insert(RenderLayers::layer(1))
-            .insert(Name::new("Parallax Camera"));
-    });
-}
-
-fn setup_world(mut commands: Commands) {
-    // spawn initial world#[serde(default)]
-    // Spawn ground (fixed)
-    let ground_bundle = RigidBodyBundle {
-        body: RigidBody::Fixed,
-        collision_group: CollisionGroups::new(
-            CollisionGroup::Wall.group() | CollisionGroup::Common.group(),
-            CollisionGroup::All.group(),
-        ),
-        ..Default::default()
-    };
+    // Moved by the parallax plugin. Following the player camera would move it twice
     commands
-        .spawn(ground_bundle)
-        .insert(Collider::cuboid(1000.0, 0.01));
+        .spawn(parallax_camera)
+        .insert(ParallaxCameraComponent::new(1))
+        .insert(RenderLayers::layer(1))
+        .insert(Name::new("Parallax Camera"));
+}
 
+fn setup_world(mut commands: Commands, config: Res<GameConfig>) {
     // Spawn delection sensor
     commands
         .spawn(Collider::cuboid(100.0, 100.0))
         .insert(TransformBundle {
-            local: Transform::from_translation(Vec3::new(-500.0, 0.0, 0.0)),
+            local: Transform::from_translation(Vec3::new(config.despawner_x, 0.0, 0.0)),
             ..Default::default()
         })
+        .insert(FollowCamera {
+            offset: config.despawner_x,
+        })
         .insert(Sensor)
+        .insert(CollisionGroup::Sensor.collision_groups())
+        // Prefab roots are kinematic bodies
+        .insert(ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_STATIC)
         .insert(Despawner)
         .insert(ActiveEvents::COLLISION_EVENTS);
+
+    commands
+        .spawn(Collider::cuboid(1000.0, 10.0))
+        .insert(TransformBundle {
+            local: Transform::from_translation(Vec3::new(0.0, KILL_PLANE_Y, 0.0)),
+            ..Default::default()
+        })
+        .insert(FollowCamera::default())
+        .insert(Sensor)
+        .insert(CollisionGroups::new(
+            CollisionGroup::Sensor.group(),
+            CollisionGroup::Player.group() | CollisionGroup::Enemy.group(),
+        ))
+        .insert(KillPlane)
+        .insert(ActiveEvents::COLLISION_EVENTS);
+}
+
+fn despawn_enemies(mut commands: Commands, mut er_contact: EventReader<Contact<Despawner, Enemy>>) {
+    for contact in er_contact.read().filter(|contact| contact.started()) {
+        commands.entity(contact.b).despawn_recursive();
+    }
 }
 
-fn collision(
+fn despawn_fallen_enemies(
     mut commands: Commands,
-    mut er_collision: EventReader<CollisionEvent>,
-    q_despawner: Query<Entity, With<Despawner>>,
-    q_enemy: Query<Entity, With<Enemy>>,
-    q_collider_children: Query<&Parent, With<Collider>>,
+    mut er_contact: EventReader<Contact<KillPlane, Enemy>>,
 ) {
-    for event in er_collision.read() {
-        if let CollisionEvent::Started(ent1, ent2, _flags) = event {
-            let (_despawner, enemy_collider) = if q_despawner.contains(*ent1) {
-                (*ent1, *ent2)
-            } else if q_despawner.contains(*ent2) {
-                (*ent2, *ent1)
-            } else {
-                continue;
-            };
-            if let Ok(parent) = q_collider_children.get(enemy_collider) {
-                if let Ok(enemy) = q_enemy.get(parent.get()) {
-                    commands.entity(enemy).despawn_recursive();
-                }
-            }
-        }
+    for contact in er_contact.read().filter(|contact| contact.started()) {
+        commands.entity(contact.b).despawn_recursive();
     }
 }
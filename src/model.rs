@@ -1,30 +1,220 @@
-use bevy::{ecs::system::EntityCommands, prelude::*};
+use bevy::{ecs::system::EntityCommands, prelude::*, sprite::Anchor};
 use bevy_2d_animations::AnimatedSprite;
-use bevy_rapier2d::geometry::ActiveEvents;
+use bevy_rapier2d::geometry::{ActiveEvents, ColliderDisabled, CollisionGroups};
 
 use std::collections::HashMap;
 
-use crate::physics::ColliderChild;
+use crate::{
+    entities::{
+        behavior::BehaviorProperties,
+        character::PhysicsProperties,
+        defeat::{DefeatProperties, Defeatable, Health},
+        player::{AirProperties, DashProperties, GroundCheck, JumpProperties},
+        projectile::{Weapon, WeaponProperties},
+        state_machine::{AnimationStateMachine, CharacterAnimationState},
+    },
+    physics::{ColliderChild, CollisionGroup, Hitbox},
+};
+
+/// Anchor of the sprite for every index of the texture atlas. Allows frames of different sizes
+/// to line up with the body
+#[derive(Component, Default, Clone)]
+pub struct FrameAnchors(pub Vec<Anchor>);
+
+/// Played by models without a state machine
+pub const IDLE_ANIMATION: &str = "idle";
+
+/// Sets of colliders that are swapped depending on the playing animation and its shown frame.
+/// The first set is the default of the character
+#[derive(Component, Default, Clone)]
+pub struct FrameHitboxes {
+    pub sets: Vec<Vec<ColliderChild>>,
+    /// Maps the name of an animation to a set
+    pub animation_sets: HashMap<String, usize>,
+    /// Maps the name of an animation and an index of the texture atlas to a set
+    pub frame_sets: HashMap<(String, usize), usize>,
+    pub active_set: usize,
+}
+
+impl FrameHitboxes {
+    fn set_for(&self, animation: &str, index: usize) -> usize {
+        self.frame_sets
+            .get(&(animation.to_string(), index))
+            .or_else(|| self.animation_sets.get(animation))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// Name of the animation selected by the state machine
+fn current_animation<'a>(
+    state: Option<(&'a AnimationStateMachine, &CharacterAnimationState)>,
+) -> Option<&'a str> {
+    state.and_then(|(state_machine, current)| {
+        current
+            .state
+            .map(|idx| state_machine.states[idx].animation.as_str())
+    })
+}
+
+#[derive(Clone)]
+pub struct FrameEvent {
+    pub animation: String,
+    /// Position in the frames of the animation
+    pub position: usize,
+    pub name: String,
+}
+
+/// Events of all animations of a model
+#[derive(Component, Default, Clone)]
+pub struct FrameEvents {
+    pub events: Vec<FrameEvent>,
+    /// Atlas indices of every animation with events. Consecutive repetitions are removed
+    pub frames: HashMap<String, Vec<usize>>,
+    /// Playing animation and the position in its frames
+    pub current: Option<(String, Option<usize>)>,
+    pub last_index: Option<usize>,
+}
+
+impl FrameEvents {
+    /// Follows the animation to the next position showing `index`. Frames shown multiple times
+    /// in one animation are told apart by searching forward from the current position
+    fn advance(&mut self, animation: &str, index: usize) -> Option<usize> {
+        if self
+            .current
+            .as_ref()
+            .map_or(true, |(current, _)| current != animation)
+        {
+            self.current = Some((animation.to_string(), None));
+            self.last_index = None;
+        }
+        if self.last_index == Some(index) {
+            return None;
+        }
+        self.last_index = Some(index);
+        let frames = self.frames.get(animation)?;
+        let (_, position) = self.current.as_mut()?;
+        let start = position.map_or(0, |position| position + 1);
+        *position = (0..frames.len())
+            .map(|offset| (start + offset) % frames.len())
+            .find(|candidate| frames[*candidate] == index);
+        *position
+    }
+}
+
+/// Sent when an animation reaches a frame with an event
+#[derive(Event, Debug)]
+pub struct AnimationEvent {
+    pub entity: Entity,
+    pub name: String,
+}
 
 #[derive(Default, Clone)]
 pub struct Model {
     pub spritesheet: SpriteSheetBundle,
     pub animation: AnimatedSprite,
     pub colliders: Vec<ColliderChild>,
+    pub anchors: Option<FrameAnchors>,
+    pub hitboxes: Option<FrameHitboxes>,
+    pub state_machine: Option<AnimationStateMachine>,
+    pub events: Option<FrameEvents>,
+    pub physics: Option<PhysicsProperties>,
+    pub behavior: Option<BehaviorProperties>,
+    pub defeat: Option<DefeatProperties>,
+    pub weapon: Option<WeaponProperties>,
+    /// Groups of all colliders set in the yaml file
+    pub collision_groups: Option<CollisionGroups>,
+    pub ground_check: Option<GroundCheck>,
+    pub jump: Option<JumpProperties>,
+    pub air: Option<AirProperties>,
+    pub dash: Option<DashProperties>,
 }
 
 impl Model {
+    /// Area covered by all colliders relative to the body
+    pub fn collider_bounds(&self) -> Option<Rect> {
+        self.colliders
+            .iter()
+            .map(|child| {
+                let aabb = child.collider.raw.compute_local_aabb();
+                let offset = child.transform.local.translation.xy();
+                Rect::new(
+                    aabb.mins.x + offset.x,
+                    aabb.mins.y + offset.y,
+                    aabb.maxs.x + offset.x,
+                    aabb.maxs.y + offset.y,
+                )
+            })
+            .reduce(|a, b| a.union(b))
+    }
+
+    /// Changes all colliders including the ones only used for some frames
+    pub fn map_colliders(&mut self, f: impl Fn(&mut ColliderChild)) {
+        self.colliders.iter_mut().for_each(&f);
+        if let Some(hitboxes) = &mut self.hitboxes {
+            hitboxes.sets.iter_mut().flatten().for_each(&f);
+        }
+    }
+
+    /// Uses the groups from the yaml file or the default groups of the layer for all colliders
+    pub fn set_collision_layer(&mut self, layer: CollisionGroup) {
+        let groups = self
+            .collision_groups
+            .unwrap_or_else(|| layer.collision_groups());
+        self.map_colliders(|collider| collider.collision_group = groups);
+    }
+
     pub fn spawn(&self, mut commands: EntityCommands) {
         commands
             .insert(self.spritesheet.clone())
-            .insert(self.animation.clone())
-            .with_children(|parent| {
-                for collider in &self.colliders {
-                    parent
-                        .spawn(collider.clone())
-                        .insert(ActiveEvents::COLLISION_EVENTS);
-                }
-            });
+            .insert(self.animation.clone());
+        if let Some(anchors) = &self.anchors {
+            commands.insert(anchors.clone());
+        }
+        if let Some(hitboxes) = &self.hitboxes {
+            commands.insert(hitboxes.clone());
+        }
+        if let Some(physics) = &self.physics {
+            physics.insert(&mut commands);
+        }
+        if let Some(behavior) = &self.behavior {
+            behavior.insert(&mut commands);
+        }
+        if let Some(defeat) = &self.defeat {
+            commands
+                .insert(Defeatable(defeat.clone()))
+                .insert(Health::new(defeat.health));
+        }
+        if let Some(weapon) = &self.weapon {
+            commands.insert(Weapon::from(weapon));
+        }
+        if let Some(ground_check) = &self.ground_check {
+            commands.insert(ground_check.clone());
+        }
+        if let Some(jump) = &self.jump {
+            commands.insert(jump.clone());
+        }
+        if let Some(air) = &self.air {
+            commands.insert(air.clone());
+        }
+        if let Some(dash) = &self.dash {
+            commands.insert(dash.clone());
+        }
+        if let Some(events) = &self.events {
+            commands.insert(events.clone());
+        }
+        if let Some(state_machine) = &self.state_machine {
+            commands
+                .insert(state_machine.clone())
+                .insert(CharacterAnimationState::default());
+        }
+        commands.with_children(|parent| {
+            for collider in &self.colliders {
+                parent
+                    .spawn(collider.clone())
+                    .insert(ActiveEvents::COLLISION_EVENTS);
+            }
+        });
     }
 }
 
@@ -32,3 +222,100 @@ impl Model {
 pub struct Models {
     pub models: HashMap<String, Model>,
 }
+
+fn update_frame_anchor(
+    mut q_sprites: Query<(&FrameAnchors, &TextureAtlas, &mut Sprite), Changed<TextureAtlas>>,
+) {
+    for (anchors, atlas, mut sprite) in &mut q_sprites {
+        if let Some(anchor) = anchors.0.get(atlas.index) {
+            if sprite.anchor != *anchor {
+                sprite.anchor = *anchor;
+            }
+        }
+    }
+}
+
+fn swap_hitboxes(
+    mut commands: Commands,
+    mut q_models: Query<(
+        Entity,
+        &mut FrameHitboxes,
+        &TextureAtlas,
+        Option<&Children>,
+        Option<(&AnimationStateMachine, &CharacterAnimationState)>,
+    )>,
+    q_hitboxes: Query<(), With<Hitbox>>,
+) {
+    for (entity, mut hitboxes, atlas, children, state) in &mut q_models {
+        let animation = current_animation(state).unwrap_or(IDLE_ANIMATION);
+        let next_set = hitboxes.set_for(animation, atlas.index);
+        if next_set == hitboxes.active_set {
+            continue;
+        }
+        hitboxes.active_set = next_set;
+        // Existing colliders are changed in place so touching colliders don't start a new
+        // collision. Unused ones are disabled
+        let mut existing = children
+            .into_iter()
+            .flatten()
+            .filter(|child| q_hitboxes.contains(**child));
+        for collider in &hitboxes.sets[next_set] {
+            match existing.next() {
+                Some(child) => {
+                    commands
+                        .entity(*child)
+                        .insert((collider.collider.clone(), collider.transform.local))
+                        .remove::<ColliderDisabled>();
+                }
+                None => {
+                    commands.entity(entity).with_children(|parent| {
+                        parent
+                            .spawn(collider.clone())
+                            .insert(ActiveEvents::COLLISION_EVENTS);
+                    });
+                }
+            }
+        }
+        for child in existing {
+            commands.entity(*child).insert(ColliderDisabled);
+        }
+    }
+}
+
+fn emit_animation_events(
+    mut q_models: Query<(
+        Entity,
+        &mut FrameEvents,
+        &TextureAtlas,
+        Option<(&AnimationStateMachine, &CharacterAnimationState)>,
+    )>,
+    mut ew_animation: EventWriter<AnimationEvent>,
+) {
+    for (entity, mut frame_events, atlas, state) in &mut q_models {
+        let animation = current_animation(state).unwrap_or(IDLE_ANIMATION);
+        let Some(position) = frame_events.advance(animation, atlas.index) else {
+            continue;
+        };
+        for event in &frame_events.events {
+            if event.position == position && event.animation == animation {
+                ew_animation.send(AnimationEvent {
+                    entity,
+                    name: event.name.clone(),
+                });
+            }
+        }
+    }
+}
+
+pub struct ModelPlugin;
+
+impl Plugin for ModelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            (update_frame_anchor, swap_hitboxes, emit_animation_events),
+        )
+        .init_resource::<Models>()
+        .add_event::<AnimationEvent>();
+    }
+}
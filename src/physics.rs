@@ -1,5 +1,6 @@
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
 
 #[derive(Bundle, Clone)]
 pub struct RigidBodyBundle {
@@ -20,11 +21,18 @@ pub struct RigidBodyBundle {
     pub collider: Collider,
 }
 
+/// Marks colliders that belong to the model of a character
+#[derive(Component, Default, Clone)]
+pub struct Hitbox;
+
 #[derive(Bundle, Clone)]
 pub struct ColliderChild {
     pub collider: Collider,
     pub transform: TransformBundle,
     pub collision_group: CollisionGroups,
+    /// Group to process collision responses with
+    pub solver_group: SolverGroups,
+    pub hitbox: Hitbox,
 }
 
 impl Default for ColliderChild {
@@ -32,10 +40,10 @@ impl Default for ColliderChild {
         Self {
             collider: Collider::default(),
             transform: TransformBundle::default(),
-            collision_group: CollisionGroups::new(
-                CollisionGroup::Common.group(),
-                CollisionGroup::Common.group(),
-            ),
+            // Replaced by the groups of the layer the model is spawned as
+            collision_group: CollisionGroups::default(),
+            solver_group: SolverGroups::default(),
+            hitbox: Hitbox,
         }
     }
 }
@@ -49,25 +57,42 @@ impl Default for RigidBodyBundle {
             mass: ColliderMassProperties::default(),
             friction: Friction::default(),
             solver_group: SolverGroups::default(),
-            collision_group: CollisionGroups::new(
-                CollisionGroup::Common.group(),
-                CollisionGroup::Common.group(),
-            ),
+            collision_group: CollisionGroup::Wall.collision_groups(),
             damping: Damping::default(),
             dominance: Dominance::default(),
         }
     }
 }
 
+/// Keeps the body moving to the left with the given speed
+#[derive(Component, Default, Clone, Copy)]
+pub struct HorizontalSpeed(pub f32);
+
+fn apply_horizontal_speed(mut q_bodies: Query<(&mut Velocity, &HorizontalSpeed)>) {
+    for (mut velocity, speed) in &mut q_bodies {
+        velocity.linvel.x = -speed.0;
+    }
+}
+
+pub struct GamePhysicsPlugin;
+
+impl Plugin for GamePhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, apply_horizontal_speed);
+    }
+}
+
 #[derive(Bundle)]
 pub struct ControllerBundle {
     pub body: RigidBody,
     pub velocity: Velocity,
     pub friction: Friction,
+    /// Only used by colliders attached directly to the body
     pub group: CollisionGroups,
 }
-impl Default for ControllerBundle {
-    fn default() -> Self {
+
+impl ControllerBundle {
+    pub fn new(layer: CollisionGroup) -> Self {
         Self {
             body: RigidBody::Dynamic,
             velocity: Velocity::zero(),
@@ -75,32 +100,125 @@ impl Default for ControllerBundle {
                 coefficient: 0.0,
                 combine_rule: CoefficientCombineRule::Min,
             },
-            group: CollisionGroups::new(
-                CollisionGroup::Common.group(),
-                CollisionGroup::Common.group(),
-            ),
+            group: layer.collision_groups(),
         }
     }
 }
 
+impl Default for ControllerBundle {
+    fn default() -> Self {
+        Self::new(CollisionGroup::Enemy)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
 pub enum CollisionGroup {
-    Common,
+    #[serde(rename = "player")]
     Player,
+    #[serde(rename = "enemy")]
     Enemy,
+    /// Ground and solid obstacles
+    #[serde(rename = "wall")]
     Wall,
+    #[serde(rename = "pickup")]
+    Pickup,
+    /// Triggers like the despawner
+    #[serde(rename = "sensor")]
+    Sensor,
+    #[serde(rename = "player_projectile")]
+    PlayerProjectile,
+    #[serde(rename = "enemy_projectile")]
+    EnemyProjectile,
+    #[serde(rename = "all")]
     All,
+    #[serde(rename = "none")]
     None,
 }
 
 impl CollisionGroup {
     pub fn group(&self) -> Group {
         match self {
-            Self::Common => Group::GROUP_1,
-            Self::Player => Group::GROUP_2,
-            Self::Enemy => Group::GROUP_3,
-            Self::Wall => Group::GROUP_4,
+            Self::Player => Group::GROUP_1,
+            Self::Enemy => Group::GROUP_2,
+            Self::Wall => Group::GROUP_3,
+            Self::Pickup => Group::GROUP_4,
+            Self::Sensor => Group::GROUP_5,
+            Self::PlayerProjectile => Group::GROUP_6,
+            Self::EnemyProjectile => Group::GROUP_7,
             Self::All => Group::ALL,
             Self::None => Group::NONE,
         }
     }
+
+    /// Layers colliders of this layer interact with by default
+    pub fn default_filter(&self) -> Group {
+        let layers: &[Self] = match self {
+            Self::Player => &[
+                Self::Enemy,
+                Self::Wall,
+                Self::Pickup,
+                Self::Sensor,
+                Self::EnemyProjectile,
+            ],
+            // Enemies pass through each other
+            Self::Enemy => &[
+                Self::Player,
+                Self::Wall,
+                Self::Sensor,
+                Self::PlayerProjectile,
+            ],
+            Self::Wall => &[Self::All],
+            Self::Pickup => &[Self::Player, Self::Sensor],
+            Self::Sensor => &[
+                Self::Enemy,
+                Self::Wall,
+                Self::Pickup,
+                Self::PlayerProjectile,
+                Self::EnemyProjectile,
+            ],
+            Self::PlayerProjectile => {
+                &[Self::Enemy, Self::Wall, Self::Sensor, Self::EnemyProjectile]
+            }
+            Self::EnemyProjectile => &[
+                Self::Player,
+                Self::Wall,
+                Self::Sensor,
+                Self::PlayerProjectile,
+            ],
+            Self::All => &[Self::All],
+            Self::None => &[Self::None],
+        };
+        layers
+            .iter()
+            .fold(Group::NONE, |filter, layer| filter | layer.group())
+    }
+
+    pub fn collision_groups(&self) -> CollisionGroups {
+        CollisionGroups::new(self.group(), self.default_filter())
+    }
+}
+
+/// Collision groups as written in the yaml files
+#[derive(Deserialize, Serialize, Clone)]
+pub struct CollisionGroupsProperties {
+    pub memberships: Vec<CollisionGroup>,
+    /// Defaults to the filter of the memberships
+    #[serde(default)]
+    pub filters: Option<Vec<CollisionGroup>>,
+}
+
+impl From<&CollisionGroupsProperties> for CollisionGroups {
+    fn from(value: &CollisionGroupsProperties) -> Self {
+        let combine = |layers: &[CollisionGroup], group: fn(&CollisionGroup) -> Group| {
+            layers
+                .iter()
+                .fold(Group::NONE, |combined, layer| combined | group(layer))
+        };
+        let memberships = combine(&value.memberships, CollisionGroup::group);
+        let filters = match &value.filters {
+            Some(filters) => combine(filters, CollisionGroup::group),
+            None => combine(&value.memberships, CollisionGroup::default_filter),
+        };
+        CollisionGroups::new(memberships, filters)
+    }
 }
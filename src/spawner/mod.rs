@@ -0,0 +1,260 @@
+use std::{collections::HashMap, time::Duration};
+
+use bevy::prelude::*;
+use bevy_rapier2d::plugin::RapierConfiguration;
+use pattern::{load_patterns, setup_patterns, PatternHandle, Patterns, PlanContext};
+use planner::{PlannedObstacle, PlayerReach, SpawnPlanner};
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    boss::no_boss_active,
+    config::GameConfig,
+    entities::{
+        enemy::{spawn_enemy, EnemyKind},
+        player::Player,
+        prefab::{spawn_prefab, Prefabs},
+    },
+    ground::GroundChunk,
+    model::Models,
+    AppState, GameState, RunProgress,
+};
+
+pub mod pattern;
+pub mod planner;
+
+/// Rules for when an enemy type is allowed to spawn
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct SpawnProperties {
+    /// Relative chance of being picked among all allowed enemies
+    pub weight: f32,
+    /// Distance the player has to travel before the enemy can appear
+    pub min_distance: f32,
+    /// Seconds since the start of the run before the enemy can appear
+    pub min_time: f32,
+    /// Maximum number of enemies of this type alive at the same time
+    pub max_concurrent: Option<usize>,
+    /// Seconds between two spawns of this type
+    pub cooldown: f32,
+}
+
+impl Default for SpawnProperties {
+    fn default() -> Self {
+        Self {
+            weight: 1.0,
+            min_distance: 0.0,
+            min_time: 0.0,
+            max_concurrent: None,
+            cooldown: 0.0,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct SpawnTable {
+    pub entries: HashMap<String, SpawnProperties>,
+}
+
+#[derive(Resource, Default)]
+pub struct SpawnerState {
+    pub last_spawn: Duration,
+    /// No enemies are spawned until the last pattern has passed the spawn point
+    pub blocked_until: Duration,
+    /// Last spawn time of every enemy type
+    pub last_spawns: HashMap<String, Duration>,
+}
+
+impl SpawnTable {
+    fn is_allowed(
+        &self,
+        name: &str,
+        progress: &RunProgress,
+        alive: usize,
+        last_spawn: Option<Duration>,
+        now: Duration,
+    ) -> bool {
+        let default_props = SpawnProperties::default();
+        let props = self.entries.get(name).unwrap_or(&default_props);
+        let cooled_down = last_spawn
+            .map(|last_spawn| (now - last_spawn).as_secs_f32() >= props.cooldown)
+            .unwrap_or(true);
+        props.weight > 0.0
+            && progress.distance >= props.min_distance
+            && progress.time >= props.min_time
+            && props.max_concurrent.map(|max| alive < max).unwrap_or(true)
+            && cooled_down
+    }
+
+    fn weight(&self, name: &str) -> f32 {
+        self.entries
+            .get(name)
+            .map(|props| props.weight)
+            .unwrap_or(1.0)
+    }
+}
+
+/// Spawns the enemy or prefab with the given name
+fn spawn_obstacle(
+    commands: &mut Commands,
+    models: &Models,
+    prefabs: &Prefabs,
+    name: &str,
+    x: f32,
+    height: f32,
+) -> Option<Entity> {
+    if let Some(model) = models.models.get(name) {
+        Some(spawn_enemy(commands, name, model, x, height))
+    } else {
+        let prefab = prefabs.prefabs.get(name)?;
+        Some(spawn_prefab(commands, prefab, models, x, height))
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_enemies(
+    mut commands: Commands,
+    time: Res<Time<Virtual>>,
+    mut state: ResMut<SpawnerState>,
+    mut planner: ResMut<SpawnPlanner>,
+    models: Res<Models>,
+    prefabs: Res<Prefabs>,
+    table: Res<SpawnTable>,
+    patterns: Res<Patterns>,
+    progress: Res<RunProgress>,
+    config: Res<GameConfig>,
+    rapier_config: Res<RapierConfiguration>,
+    q_enemies: Query<&EnemyKind>,
+    q_player: Query<&Transform, With<Player>>,
+    q_chunks: Query<&GroundChunk>,
+    player: Res<PlayerReach>,
+) {
+    if time.elapsed() < state.blocked_until {
+        return;
+    }
+    let next_spawn_ms = rand::thread_rng().gen_range(800..1500);
+    // Generate new enemys
+    if time.elapsed() - state.last_spawn > Duration::from_millis(next_spawn_ms) {
+        let player_x = q_player
+            .get_single()
+            .map(|transform| transform.translation.x)
+            .unwrap_or_default();
+        let gravity = -rapier_config.gravity.y;
+        let arc = player.arc(config.jump_velocity, gravity);
+        // Relative to the distance of the run, which the camera and the player follow
+        let spawn_x = progress.distance + config.spawn_x;
+        let mut alive: HashMap<String, usize> = HashMap::new();
+        for kind in &q_enemies {
+            *alive.entry(kind.0.clone()).or_default() += 1;
+        }
+
+        let context = PlanContext {
+            models: &models,
+            prefabs: &prefabs,
+            player: &player,
+            arc,
+            gravity,
+            scroll_speed: config.scroll_speed,
+            spawn_x,
+            spawn_distance: spawn_x - player_x,
+            now: progress.time,
+            table: &table,
+            state: &state,
+            progress: &progress,
+            alive,
+            elapsed: time.elapsed(),
+            chunks: q_chunks.iter().collect(),
+        };
+        planner.set_pits(&context.pits(), &player, progress.time);
+        if rand::thread_rng().gen_bool(patterns.pattern_chance.clamp(0.0, 1.0)) {
+            let tier = patterns.tier(progress.distance);
+            if let Some((pattern, next_planner)) = patterns.choose(tier, &planner, &context) {
+                info!("Spawning pattern {}", pattern.name);
+                // Planning made sure there is ground below every entry
+                let grounds: Vec<f32> = pattern
+                    .entries
+                    .iter()
+                    .map(|entry| context.ground_height(entry.x_offset).unwrap_or_default())
+                    .collect();
+                for (entry, ground) in pattern.entries.iter().zip(grounds) {
+                    spawn_obstacle(
+                        &mut commands,
+                        &models,
+                        &prefabs,
+                        &entry.enemy,
+                        spawn_x + entry.x_offset,
+                        ground + entry.height,
+                    );
+                    state
+                        .last_spawns
+                        .insert(entry.enemy.clone(), time.elapsed());
+                }
+                *planner = next_planner;
+                state.last_spawn = time.elapsed();
+                // Continue with the next spawn once the whole pattern is on screen
+                state.blocked_until = time.elapsed()
+                    + Duration::from_secs_f32(pattern.length() / config.scroll_speed);
+                return;
+            }
+        }
+        // Nothing spawns above pits. Retry once the spawn point is above ground again
+        let Some(ground) = context.ground_height(0.0) else {
+            return;
+        };
+        let allowed: Vec<&String> = table
+            .entries
+            .keys()
+            .filter(|name| context.is_allowed(name, 0))
+            .collect();
+        let clearable: Vec<(&String, PlannedObstacle)> = allowed
+            .iter()
+            .filter_map(|name| Some((*name, context.obstacle(name, 0.0, 0.0)?)))
+            .filter(|(_name, obstacle)| {
+                planner.is_clearable(obstacle, &player, &context.arc, progress.time)
+            })
+            .collect();
+        // Retry next frame if only the planner rejected the enemies
+        if !allowed.is_empty() && clearable.is_empty() {
+            return;
+        }
+        state.last_spawn = time.elapsed();
+        // spawn new
+        if let Ok((name, obstacle)) = clearable
+            .choose_weighted(&mut rand::thread_rng(), |(name, _obstacle)| {
+                table.weight(name)
+            })
+        {
+            let name = (*name).clone();
+            spawn_obstacle(&mut commands, &models, &prefabs, &name, spawn_x, ground);
+            planner.add(obstacle, &player, progress.time);
+            state.last_spawns.insert(name, time.elapsed());
+        }
+    }
+}
+
+fn reset_spawner(mut commands: Commands) {
+    commands.insert_resource(SpawnerState::default());
+    commands.insert_resource(SpawnPlanner::default());
+}
+
+pub struct SpawnerPlugin;
+
+impl Plugin for SpawnerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_patterns)
+            .add_systems(Update, setup_patterns)
+            .add_systems(
+                Update,
+                spawn_enemies
+                    .run_if(in_state(GameState::Running))
+                    .run_if(no_boss_active)
+                    .run_if(resource_exists::<PlayerReach>),
+            )
+            .add_systems(OnEnter(AppState::Game), reset_spawner)
+            .init_resource::<SpawnTable>()
+            .init_resource::<SpawnerState>()
+            .init_resource::<SpawnPlanner>()
+            .init_resource::<Patterns>()
+            .init_resource::<PatternHandle>();
+    }
+}
@@ -0,0 +1,246 @@
+use std::{collections::HashMap, time::Duration};
+
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entities::prefab::Prefabs,
+    ground::{surface_height, GroundChunk},
+    model::Models,
+    RunProgress,
+};
+
+use super::{
+    planner::{JumpArc, PlannedObstacle, PlayerReach, SpawnPlanner},
+    SpawnTable, SpawnerState,
+};
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct PatternEntry {
+    /// Name of an enemy or a prefab
+    pub enemy: String,
+    /// Distance to the first enemy of the pattern
+    pub x_offset: f32,
+    /// Added to the spawn height of the enemy. Only flying enemies stay at this height
+    pub height: f32,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct PatternProperties {
+    pub name: String,
+    /// Lowest difficulty tier the pattern is used in
+    pub min_tier: u32,
+    pub max_tier: Option<u32>,
+    pub weight: f32,
+    pub entries: Vec<PatternEntry>,
+}
+
+impl Default for PatternProperties {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            min_tier: 0,
+            max_tier: None,
+            weight: 1.0,
+            entries: vec![],
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Asset, TypePath, Resource, Clone)]
+#[serde(default)]
+pub struct Patterns {
+    /// Distance the player has to travel to reach the next difficulty tier
+    pub tier_distance: f32,
+    /// Chance to spawn a pattern instead of a single enemy
+    pub pattern_chance: f64,
+    pub patterns: Vec<PatternProperties>,
+}
+
+impl Default for Patterns {
+    fn default() -> Self {
+        Self {
+            tier_distance: 1000.0,
+            pattern_chance: 0.0,
+            patterns: vec![],
+        }
+    }
+}
+
+/// Everything needed to check if enemies can be spawned right now
+pub struct PlanContext<'a> {
+    pub models: &'a Models,
+    pub prefabs: &'a Prefabs,
+    pub player: &'a PlayerReach,
+    pub arc: JumpArc,
+    /// Downwards acceleration as a positive value
+    pub gravity: f32,
+    pub scroll_speed: f32,
+    pub spawn_x: f32,
+    /// Distance between the spawn point and the player
+    pub spawn_distance: f32,
+    /// Seconds since the start of the run
+    pub now: f32,
+    pub table: &'a SpawnTable,
+    pub state: &'a SpawnerState,
+    pub progress: &'a RunProgress,
+    /// Number of alive enemies of every type
+    pub alive: HashMap<String, usize>,
+    pub elapsed: Duration,
+    pub chunks: Vec<&'a GroundChunk>,
+}
+
+impl PlanContext<'_> {
+    /// Whether the spawn table allows another enemy of the type with `planned` of them about to
+    /// be spawned
+    pub fn is_allowed(&self, name: &str, planned: usize) -> bool {
+        let alive = self.alive.get(name).copied().unwrap_or(0) + planned;
+        self.table.is_allowed(
+            name,
+            self.progress,
+            alive,
+            self.state.last_spawns.get(name).copied(),
+            self.elapsed,
+        )
+    }
+
+    /// Height of the ground `x_offset` behind the spawn point. `None` above pits
+    pub fn ground_height(&self, x_offset: f32) -> Option<f32> {
+        surface_height(self.chunks.iter().copied(), self.spawn_x + x_offset)
+    }
+
+    /// Pits of the generated ground the player hasn't passed yet
+    pub fn pits(&self) -> Vec<PlannedObstacle> {
+        let player_x = self.spawn_x - self.spawn_distance;
+        self.chunks
+            .iter()
+            .flat_map(|chunk| &chunk.pits)
+            .filter(|(_start_x, end_x)| *end_x > player_x)
+            .map(|(start_x, end_x)| {
+                PlannedObstacle::pit(*start_x, *end_x, player_x, self.scroll_speed)
+            })
+            .collect()
+    }
+
+    /// Plans an enemy or prefab spawned `x_offset` behind the spawn point
+    pub fn obstacle(&self, name: &str, x_offset: f32, height: f32) -> Option<PlannedObstacle> {
+        let distance = self.spawn_distance + x_offset;
+        if let Some(model) = self.models.models.get(name) {
+            Some(PlannedObstacle::new(
+                model,
+                self.scroll_speed,
+                self.gravity,
+                distance,
+                height,
+            ))
+        } else {
+            self.prefabs.prefabs.get(name).map(|prefab| {
+                PlannedObstacle::from_prefab(
+                    prefab,
+                    self.models,
+                    self.scroll_speed,
+                    distance,
+                    height,
+                )
+            })
+        }
+    }
+}
+
+impl PatternProperties {
+    fn fits_tier(&self, tier: u32) -> bool {
+        tier >= self.min_tier && self.max_tier.map(|max| tier <= max).unwrap_or(true)
+    }
+
+    /// Distance between the first and the last enemy
+    pub fn length(&self) -> f32 {
+        self.entries
+            .iter()
+            .map(|entry| entry.x_offset)
+            .fold(0.0, f32::max)
+    }
+
+    /// Returns the planner with all enemies of the pattern added if every enemy is allowed by
+    /// the spawn table, has ground below it and can be cleared
+    pub fn plan(&self, planner: &SpawnPlanner, context: &PlanContext) -> Option<SpawnPlanner> {
+        let mut planner = planner.clone();
+        let mut planned: HashMap<&str, usize> = HashMap::new();
+        for entry in &self.entries {
+            let count = planned.entry(entry.enemy.as_str()).or_default();
+            if !context.is_allowed(&entry.enemy, *count) {
+                return None;
+            }
+            *count += 1;
+            context.ground_height(entry.x_offset)?;
+            let obstacle = context.obstacle(&entry.enemy, entry.x_offset, entry.height)?;
+            if !planner.is_clearable(&obstacle, context.player, &context.arc, context.now) {
+                return None;
+            }
+            planner.add(&obstacle, context.player, context.now);
+        }
+        Some(planner)
+    }
+}
+
+impl Patterns {
+    /// Length of the longest pattern
+    pub fn max_length(&self) -> f32 {
+        self.patterns
+            .iter()
+            .map(PatternProperties::length)
+            .fold(0.0, f32::max)
+    }
+
+    pub fn tier(&self, distance: f32) -> u32 {
+        (distance / self.tier_distance.max(1.0)) as u32
+    }
+
+    /// Picks a random pattern of the tier that can be cleared. Returns the pattern and the
+    /// planner containing its enemies
+    pub fn choose(
+        &self,
+        tier: u32,
+        planner: &SpawnPlanner,
+        context: &PlanContext,
+    ) -> Option<(&PatternProperties, SpawnPlanner)> {
+        let candidates: Vec<(&PatternProperties, SpawnPlanner)> = self
+            .patterns
+            .iter()
+            .filter(|pattern| pattern.fits_tier(tier) && !pattern.entries.is_empty())
+            .filter_map(|pattern| {
+                pattern
+                    .plan(planner, context)
+                    .map(|planner| (pattern, planner))
+            })
+            .collect();
+        candidates
+            .choose_weighted(&mut rand::thread_rng(), |(pattern, _planner)| {
+                pattern.weight
+            })
+            .ok()
+            .cloned()
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct PatternHandle(Handle<Patterns>);
+
+pub fn load_patterns(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let patterns: Handle<Patterns> = asset_server.load("spawn.patterns.yaml");
+
+    commands.insert_resource(PatternHandle(patterns));
+}
+
+pub fn setup_patterns(
+    mut commands: Commands,
+    mut pattern_assets: ResMut<Assets<Patterns>>,
+    pattern_handle: Res<PatternHandle>,
+) {
+    if let Some(patterns) = pattern_assets.remove(pattern_handle.0.id()) {
+        info!("Loaded {} spawn patterns", patterns.patterns.len());
+        commands.insert_resource(patterns);
+    }
+}
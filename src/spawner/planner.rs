@@ -0,0 +1,390 @@
+use bevy::prelude::*;
+
+use crate::{
+    entities::{
+        behavior::BehaviorProperties,
+        player::{AirProperties, JumpProperties},
+        prefab::PrefabProperties,
+    },
+    model::{Model, Models},
+};
+
+/// Seconds the player needs between landing and jumping again
+const REACTION_TIME: f32 = 0.15;
+
+/// Flight of a jump from the ground. Extra jumps are used at the apex of the previous jump, so
+/// the height only decreases after the last one
+#[derive(Clone, Debug)]
+pub struct JumpArc {
+    /// Upwards velocity of every jump in order
+    velocities: Vec<f32>,
+    /// Downwards acceleration as a positive value
+    gravity: f32,
+}
+
+impl JumpArc {
+    pub fn new(velocities: Vec<f32>, gravity: f32) -> Self {
+        Self {
+            velocities: velocities.into_iter().filter(|v| *v > 0.0).collect(),
+            gravity: gravity.max(f32::EPSILON),
+        }
+    }
+
+    /// Seconds until the highest point and its height
+    fn apex(&self) -> (f32, f32) {
+        self.velocities
+            .iter()
+            .fold((0.0, 0.0), |(time, height), v| {
+                (
+                    time + v / self.gravity,
+                    height + v * v / (2.0 * self.gravity),
+                )
+            })
+    }
+
+    pub fn apex_height(&self) -> f32 {
+        self.apex().1
+    }
+
+    /// Seconds in the air
+    pub fn airtime(&self) -> f32 {
+        let (time, height) = self.apex();
+        time + (2.0 * height / self.gravity).sqrt()
+    }
+
+    /// Seconds the feet stay above `height`. Zero if the arc doesn't reach it
+    pub fn time_above(&self, height: f32) -> f32 {
+        let (apex_time, apex_height) = self.apex();
+        if height >= apex_height {
+            return 0.0;
+        }
+        let height = height.max(0.0);
+        let g = self.gravity;
+        let (mut start_time, mut start_height, mut rise) = (0.0, 0.0, 0.0);
+        for v in &self.velocities {
+            let peak = start_height + v * v / (2.0 * g);
+            if height < peak {
+                // First root of start_height + v * t - g * t^2 / 2 = height
+                rise = start_time + (v - (v * v - 2.0 * g * (height - start_height)).sqrt()) / g;
+                break;
+            }
+            start_time += v / g;
+            start_height = peak;
+        }
+        let fall = apex_time + (2.0 * (apex_height - height) / g).sqrt();
+        fall - rise
+    }
+}
+
+/// Size and jump of the loaded player. Obstacles are only planned once it exists
+#[derive(Resource, Clone)]
+pub struct PlayerReach {
+    pub size: Vec2,
+    pub jump: JumpProperties,
+    pub air: AirProperties,
+}
+
+impl PlayerReach {
+    pub fn from_model(model: &Model) -> Self {
+        Self {
+            size: model.collider_bounds().unwrap_or_default().size(),
+            jump: model.jump.clone().unwrap_or_default(),
+            air: model.air.clone().unwrap_or_default(),
+        }
+    }
+
+    /// Highest jump including all extra jumps. The player is expected to hold `Jump`
+    pub fn arc(&self, default_velocity: f32, gravity: f32) -> JumpArc {
+        let velocity = self.jump.velocity.unwrap_or(default_velocity);
+        let extra_velocity = self.air.extra_jump_velocity.unwrap_or(velocity);
+        let mut velocities = vec![velocity];
+        velocities.extend(std::iter::repeat(extra_velocity).take(self.air.extra_jumps as usize));
+        JumpArc::new(velocities, gravity)
+    }
+}
+
+/// Obstacle that is about to be spawned
+pub struct PlannedObstacle {
+    /// Horizontal speed towards the player
+    pub speed: f32,
+    pub width: f32,
+    /// Highest point of the obstacle above the ground
+    pub top: f32,
+    /// Lowest point of the obstacle above the ground
+    pub bottom: f32,
+    /// Distance between the spawn point and the player
+    pub distance: f32,
+}
+
+impl PlannedObstacle {
+    /// `gravity` is used for the height of hops
+    pub fn new(model: &Model, scroll_speed: f32, gravity: f32, distance: f32, height: f32) -> Self {
+        let mut bounds = model.collider_bounds().unwrap_or_default();
+        let own_speed = model
+            .physics
+            .as_ref()
+            .and_then(|physics| physics.speed)
+            .unwrap_or(0.0);
+        // Assume the worst case for behaviors changing the speed or the height
+        let own_speed = match &model.behavior {
+            Some(BehaviorProperties::Charger(charger)) => charger.max_speed,
+            Some(BehaviorProperties::Patroller(patroller)) => patroller.speed,
+            _ => own_speed,
+        };
+        match &model.behavior {
+            Some(BehaviorProperties::Hopper(hopper)) => {
+                let gravity_scale = model
+                    .physics
+                    .as_ref()
+                    .map_or(1.0, |physics| physics.gravity_scale);
+                bounds.max.y += hopper.jump_velocity.powi(2) / (2.0 * gravity * gravity_scale);
+            }
+            Some(BehaviorProperties::SineFlyer(flyer)) => {
+                bounds.max.y += flyer.amplitude.abs();
+                bounds.min.y -= flyer.amplitude.abs();
+            }
+            _ => (),
+        }
+        let height = model.spritesheet.transform.translation.y + height;
+        Self::from_bounds(bounds, scroll_speed + own_speed, distance, height)
+    }
+
+    /// Prefabs don't move on their own
+    pub fn from_prefab(
+        prefab: &PrefabProperties,
+        models: &Models,
+        scroll_speed: f32,
+        distance: f32,
+        height: f32,
+    ) -> Self {
+        let bounds = prefab.bounds(models).unwrap_or_default();
+        Self::from_bounds(bounds, scroll_speed, distance, height)
+    }
+
+    /// Pits have to be jumped over like any obstacle on the ground
+    pub fn pit(start_x: f32, end_x: f32, player_x: f32, scroll_speed: f32) -> Self {
+        let half_width = (end_x - start_x) / 2.0;
+        let bounds = Rect::new(-half_width, 0.0, half_width, 0.0);
+        let distance = start_x + half_width - player_x;
+        Self::from_bounds(bounds, scroll_speed, distance, 0.0)
+    }
+
+    /// `bounds` are relative to the obstacle placed at `height` above the ground
+    fn from_bounds(bounds: Rect, speed: f32, distance: f32, height: f32) -> Self {
+        Self {
+            speed: speed.max(f32::EPSILON),
+            width: bounds.width(),
+            top: height + bounds.max.y,
+            bottom: height + bounds.min.y,
+            distance,
+        }
+    }
+
+    /// The player can pass below the obstacle without jumping
+    fn is_overhead(&self, player: &PlayerReach) -> bool {
+        self.bottom > player.size.y
+    }
+
+    /// Run time in which the obstacle and the player overlap horizontally
+    fn passage(&self, player: &PlayerReach, now: f32) -> Passage {
+        let arrival = now + self.distance / self.speed;
+        let half_duration = (self.width + player.size.x) / self.speed / 2.0;
+        Passage {
+            start: arrival - half_duration,
+            end: arrival + half_duration,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Passage {
+    start: f32,
+    end: f32,
+}
+
+/// Keeps track of when spawned obstacles pass the player and rejects obstacles that can't be
+/// cleared with a single jump
+#[derive(Resource, Default, Clone)]
+pub struct SpawnPlanner {
+    passages: Vec<Passage>,
+    /// Pits of the generated ground. Replaced before every spawn
+    pits: Vec<Passage>,
+}
+
+impl SpawnPlanner {
+    pub fn is_clearable(
+        &self,
+        obstacle: &PlannedObstacle,
+        player: &PlayerReach,
+        arc: &JumpArc,
+        now: f32,
+    ) -> bool {
+        if obstacle.is_overhead(player) {
+            return true;
+        }
+        let passage = obstacle.passage(player, now);
+        // The feet have to stay above the obstacle while it passes below
+        if arc.time_above(obstacle.top) < passage.end - passage.start {
+            return false;
+        }
+        // Enough time to land and jump again between two obstacles
+        let min_gap = arc.airtime() + REACTION_TIME;
+        self.passages.iter().chain(&self.pits).all(|other| {
+            passage.start - other.end >= min_gap || other.start - passage.end >= min_gap
+        })
+    }
+
+    pub fn add(&mut self, obstacle: &PlannedObstacle, player: &PlayerReach, now: f32) {
+        // Obstacles that already passed the player can't collide with new ones
+        self.passages.retain(|passage| passage.end > now);
+        if !obstacle.is_overhead(player) {
+            self.passages.push(obstacle.passage(player, now));
+        }
+    }
+
+    pub fn set_pits(&mut self, pits: &[PlannedObstacle], player: &PlayerReach, now: f32) {
+        self.pits = pits.iter().map(|pit| pit.passage(player, now)).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAVITY: f32 = 800.0;
+
+    fn player() -> PlayerReach {
+        PlayerReach {
+            size: Vec2::new(10.0, 14.0),
+            jump: JumpProperties::default(),
+            air: AirProperties::default(),
+        }
+    }
+
+    fn obstacle(width: f32, top: f32, distance: f32) -> PlannedObstacle {
+        PlannedObstacle {
+            speed: 100.0,
+            width,
+            top,
+            bottom: 0.0,
+            distance,
+        }
+    }
+
+    #[test]
+    fn single_jump() {
+        let arc = JumpArc::new(vec![300.0], GRAVITY);
+        assert!((arc.apex_height() - 56.25).abs() < 1e-3);
+        assert!((arc.airtime() - 0.75).abs() < 1e-4);
+        assert!((arc.time_above(0.0) - arc.airtime()).abs() < 1e-4);
+        assert_eq!(arc.time_above(arc.apex_height()), 0.0);
+        // 2 * sqrt(v^2 - 2 * g * h) / g
+        let expected = 2.0 * (300.0f32.powi(2) - 2.0 * GRAVITY * 20.0).sqrt() / GRAVITY;
+        assert!((arc.time_above(20.0) - expected).abs() < 1e-4);
+    }
+
+    #[test]
+    fn extra_jump_at_apex() {
+        let arc = JumpArc::new(vec![300.0, 300.0], GRAVITY);
+        assert!((arc.apex_height() - 112.5).abs() < 1e-3);
+        assert!((arc.airtime() - (0.75 + (225.0f32 / GRAVITY).sqrt())).abs() < 1e-4);
+        // Above the first apex only during the second jump
+        let second = JumpArc::new(vec![300.0], GRAVITY);
+        let above_first_apex = arc.time_above(56.25 + 20.0);
+        assert!((above_first_apex - second.time_above(20.0)).abs() < 1e-3);
+        assert!(arc.time_above(60.0) > 0.0);
+    }
+
+    #[test]
+    fn player_arc_uses_jump_properties() {
+        let mut player = player();
+        player.jump.velocity = Some(200.0);
+        player.air.extra_jumps = 1;
+        player.air.extra_jump_velocity = Some(100.0);
+        let arc = player.arc(300.0, GRAVITY);
+        let expected = JumpArc::new(vec![200.0, 100.0], GRAVITY);
+        assert_eq!(arc.apex_height(), expected.apex_height());
+        assert_eq!(arc.airtime(), expected.airtime());
+    }
+
+    #[test]
+    fn rejects_obstacles_above_apex() {
+        let arc = JumpArc::new(vec![300.0], GRAVITY);
+        let planner = SpawnPlanner::default();
+        assert!(planner.is_clearable(&obstacle(10.0, 20.0, 300.0), &player(), &arc, 0.0));
+        assert!(!planner.is_clearable(&obstacle(10.0, 60.0, 300.0), &player(), &arc, 0.0));
+    }
+
+    #[test]
+    fn rejects_obstacles_too_wide_to_jump_over() {
+        let arc = JumpArc::new(vec![300.0], GRAVITY);
+        let planner = SpawnPlanner::default();
+        // Below the apex but passes too slowly below the feet
+        let time_above = arc.time_above(50.0);
+        let width = time_above * 100.0;
+        assert!(!planner.is_clearable(&obstacle(width, 50.0, 300.0), &player(), &arc, 0.0));
+        assert!(planner.is_clearable(&obstacle(1.0, 20.0, 300.0), &player(), &arc, 0.0));
+    }
+
+    #[test]
+    fn overhead_obstacles_are_always_clearable() {
+        let arc = JumpArc::new(vec![300.0], GRAVITY);
+        let planner = SpawnPlanner::default();
+        let mut high = obstacle(100.0, 200.0, 300.0);
+        high.bottom = 20.0;
+        assert!(planner.is_clearable(&high, &player(), &arc, 0.0));
+        high.bottom = 10.0;
+        assert!(!planner.is_clearable(&high, &player(), &arc, 0.0));
+    }
+
+    #[test]
+    fn keeps_a_gap_between_both_obstacles() {
+        let arc = JumpArc::new(vec![300.0], GRAVITY);
+        let player = player();
+        let mut planner = SpawnPlanner::default();
+        let wide = obstacle(30.0, 10.0, 300.0);
+        planner.add(&wide, &player, 0.0);
+        let min_gap = arc.airtime() + REACTION_TIME;
+        // Distance between the passages of both obstacles in seconds
+        let gap = |distance: f32| {
+            let first = wide.passage(&player, 0.0);
+            let second = obstacle(2.0, 10.0, distance).passage(&player, 0.0);
+            second.start - first.end
+        };
+        // Half of both passages, widened by the player
+        let edges = (30.0 + 10.0) / 2.0 + (2.0 + 10.0) / 2.0;
+        let close = 300.0 + (min_gap - 0.05) * 100.0 + edges;
+        let far = 300.0 + (min_gap + 0.05) * 100.0 + edges;
+        assert!(gap(close) < min_gap);
+        assert!(gap(far) >= min_gap);
+        assert!(!planner.is_clearable(&obstacle(2.0, 10.0, close), &player, &arc, 0.0));
+        assert!(planner.is_clearable(&obstacle(2.0, 10.0, far), &player, &arc, 0.0));
+    }
+
+    #[test]
+    fn keeps_a_gap_to_pits() {
+        let arc = JumpArc::new(vec![300.0], GRAVITY);
+        let player = player();
+        let mut planner = SpawnPlanner::default();
+        planner.set_pits(
+            &[PlannedObstacle::pit(300.0, 332.0, 0.0, 100.0)],
+            &player,
+            0.0,
+        );
+        assert!(!planner.is_clearable(&obstacle(10.0, 10.0, 380.0), &player, &arc, 0.0));
+        assert!(planner.is_clearable(&obstacle(10.0, 10.0, 500.0), &player, &arc, 0.0));
+        planner.set_pits(&[], &player, 0.0);
+        assert!(planner.is_clearable(&obstacle(10.0, 10.0, 380.0), &player, &arc, 0.0));
+    }
+
+    #[test]
+    fn forgets_passed_obstacles() {
+        let arc = JumpArc::new(vec![300.0], GRAVITY);
+        let player = player();
+        let mut planner = SpawnPlanner::default();
+        planner.add(&obstacle(10.0, 10.0, 100.0), &player, 0.0);
+        assert!(!planner.is_clearable(&obstacle(10.0, 10.0, 100.0), &player, &arc, 0.0));
+        planner.add(&obstacle(10.0, 10.0, 500.0), &player, 5.0);
+        assert!(planner.is_clearable(&obstacle(10.0, 10.0, 100.0), &player, &arc, 5.0));
+    }
+}
@@ -0,0 +1,162 @@
+use bevy::prelude::*;
+
+use crate::{
+    boss::{Boss, BossEncounter},
+    entities::{
+        defeat::Health,
+        player::{DashProperties, DashState},
+    },
+    RunProgress,
+};
+
+#[derive(Component)]
+pub struct HudMarker;
+
+#[derive(Component)]
+pub struct ScoreText;
+
+/// Only shown during boss fights
+#[derive(Component)]
+pub struct BossHealthBar;
+
+#[derive(Component)]
+pub struct BossHealthFill;
+
+/// Fills up until the next dash is ready
+#[derive(Component)]
+pub struct DashCooldownFill;
+
+pub fn hud_setup(mut commands: Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                padding: UiRect::all(Val::Px(10.0)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(HudMarker)
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle::from_section(
+                    "0",
+                    TextStyle {
+                        font_size: 30.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                        ..Default::default()
+                    },
+                ))
+                .insert(ScoreText);
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Px(80.0),
+                        height: Val::Px(8.0),
+                        margin: UiRect::top(Val::Px(5.0)),
+                        border: UiRect::all(Val::Px(1.0)),
+                        ..Default::default()
+                    },
+                    border_color: BorderColor(Color::BLACK),
+                    background_color: BackgroundColor(Color::rgb(0.2, 0.2, 0.2)),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                width: Val::Percent(100.0),
+                                height: Val::Percent(100.0),
+                                ..Default::default()
+                            },
+                            background_color: BackgroundColor(Color::rgb(0.2, 0.6, 0.9)),
+                            ..Default::default()
+                        })
+                        .insert(DashCooldownFill);
+                });
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Percent(50.0),
+                        height: Val::Px(16.0),
+                        margin: UiRect::top(Val::Px(10.0)),
+                        border: UiRect::all(Val::Px(2.0)),
+                        ..Default::default()
+                    },
+                    border_color: BorderColor(Color::BLACK),
+                    background_color: BackgroundColor(Color::rgb(0.2, 0.2, 0.2)),
+                    visibility: Visibility::Hidden,
+                    ..Default::default()
+                })
+                .insert(BossHealthBar)
+                .with_children(|parent| {
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                width: Val::Percent(100.0),
+                                height: Val::Percent(100.0),
+                                ..Default::default()
+                            },
+                            background_color: BackgroundColor(Color::rgb(0.8, 0.1, 0.1)),
+                            ..Default::default()
+                        })
+                        .insert(BossHealthFill);
+                });
+        });
+}
+
+pub fn hud_cleanup(mut commands: Commands, q_hud: Query<Entity, With<HudMarker>>) {
+    for entity in &q_hud {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+pub fn update_score(progress: Res<RunProgress>, mut q_text: Query<&mut Text, With<ScoreText>>) {
+    if !progress.is_changed() {
+        return;
+    }
+    for mut text in &mut q_text {
+        text.sections[0].value = progress.score.to_string();
+    }
+}
+
+pub fn update_dash_cooldown(
+    q_player: Query<(&DashState, &DashProperties)>,
+    mut q_fill: Query<&mut Style, With<DashCooldownFill>>,
+) {
+    let Ok((dash, props)) = q_player.get_single() else {
+        return;
+    };
+    let ready = if props.cooldown > 0.0 {
+        1.0 - dash.cooldown / props.cooldown
+    } else {
+        1.0
+    };
+    for mut style in &mut q_fill {
+        style.width = Val::Percent(ready.clamp(0.0, 1.0) * 100.0);
+    }
+}
+
+pub fn update_boss_health(
+    encounter: Res<BossEncounter>,
+    q_bosses: Query<&Health, With<Boss>>,
+    mut q_bar: Query<&mut Visibility, With<BossHealthBar>>,
+    mut q_fill: Query<&mut Style, With<BossHealthFill>>,
+) {
+    let health = encounter.active.and_then(|boss| q_bosses.get(boss).ok());
+    for mut visibility in &mut q_bar {
+        *visibility = if health.is_some() {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+    if let Some(health) = health {
+        for mut style in &mut q_fill {
+            style.width = Val::Percent(health.fraction() * 100.0);
+        }
+    }
+}
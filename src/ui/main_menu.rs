@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use crate::AppState;
+use crate::{AppState, RunProgress};
 
 #[derive(Component)]
 pub struct MainMenuMarker;
@@ -107,12 +107,13 @@ pub struct GameoverMenuMarker;
 #[derive(Component)]
 pub struct MainMenuButton;
 
-pub fn gameover_menu_setup(mut commands: Commands) {
+pub fn gameover_menu_setup(mut commands: Commands, progress: Res<RunProgress>) {
     commands
         .spawn(NodeBundle {
             style: Style {
                 width: Val::Percent(100.0),
                 height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
                 align_items: AlignItems::Center,
                 justify_content: JustifyContent::Center,
                 ..Default::default()
@@ -120,6 +121,18 @@ pub fn gameover_menu_setup(mut commands: Commands) {
             ..Default::default()
         })
         .insert(GameoverMenuMarker)
+        .with_children(|parent| {
+            if let Some(death) = progress.death {
+                parent.spawn(TextBundle::from_section(
+                    death.message(),
+                    TextStyle {
+                        font_size: 30.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                        ..Default::default()
+                    },
+                ));
+            }
+        })
         .with_children(|parent| {
             parent
                 .spawn(ButtonBundle {
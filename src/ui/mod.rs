@@ -1,8 +1,10 @@
 use bevy::prelude::*;
+use hud::*;
 use main_menu::*;
 
 use crate::AppState;
 
+pub mod hud;
 pub mod main_menu;
 
 pub struct GameUiPlugin;
@@ -13,6 +15,13 @@ impl Plugin for GameUiPlugin {
             .add_systems(OnExit(AppState::MainMenu), main_menu_cleanup)
             .add_systems(OnEnter(AppState::GameOver), gameover_menu_setup)
             .add_systems(OnExit(AppState::GameOver), gameover_menu_cleanup)
+            .add_systems(OnEnter(AppState::Game), hud_setup)
+            .add_systems(OnExit(AppState::Game), hud_cleanup)
+            .add_systems(
+                Update,
+                (update_score, update_boss_health, update_dash_cooldown)
+                    .run_if(in_state(AppState::Game)),
+            )
             .add_systems(
                 Update,
                 handle_new_game_button.run_if(in_state(AppState::MainMenu)),